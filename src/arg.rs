@@ -1,5 +1,9 @@
 //! Helper trait to convert Rust types to arguments
+use alloc::borrow::Cow;
 use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Error, NinjaStr, escape_build};
 
 /// Convert something to an argument
 ///
@@ -13,6 +17,7 @@ use alloc::string::String;
 /// # Panic
 /// Conversion from `OsString`, `Path` and byte slices will panic if they are not valid UTF-8.
 /// These implementations are only available with the `std` feature.
+/// Use [`TryToArg`] and the `try_` methods to handle the error instead.
 ///
 /// # Mixing types
 /// If you need to mix types within the same list, you can use the [`args!`](crate::args) macro.
@@ -47,6 +52,81 @@ pub trait ToArg {
     /// This will panic if the type is not valid UTF-8.
     /// Only implementations enabled by the `std` feature will panic.
     fn to_arg(self) -> String;
+
+    /// Convert the type to an argument for a path, escaped with [`escape_build`].
+    ///
+    /// This is used when auto-escaping is enabled (see [`Ninja::auto_escape`](crate::Ninja::auto_escape)).
+    /// [`Raw`](crate::Raw) overrides this to not escape.
+    fn to_path_arg(self) -> String
+    where
+        Self: Sized,
+    {
+        let arg = self.to_arg();
+        match escape_build(&arg) {
+            Cow::Owned(escaped) => escaped,
+            Cow::Borrowed(_) => arg,
+        }
+    }
+
+    /// Convert the type to ninja text that keeps track of what needs escaping.
+    ///
+    /// By default, the argument is already escaped. [`Literal`](crate::Literal)
+    /// and [`VarRef`](crate::VarRef) override this.
+    fn to_ninja_str(self) -> NinjaStr
+    where
+        Self: Sized,
+    {
+        NinjaStr::new().raw(self)
+    }
+}
+
+/// Convert something to an argument, or return an error if it can't be converted
+///
+/// This is the fallible version of [`ToArg`], for the types that panic in [`ToArg`]
+/// when they are not valid UTF-8. It is used by the `try_` methods, such as
+/// [`Ninja::try_rule`](crate::Ninja::try_rule).
+///
+/// # Example
+/// ```rust
+/// # #[cfg(all(feature = "std", unix))]
+/// # {
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use std::path::Path;
+///
+/// use ninja_writer::*;
+///
+/// let bad = Path::new(OsStr::from_bytes(b"bad\xff.c"));
+/// assert_eq!(bad.try_to_arg(), Err(Error::InvalidUtf8Path(bad.to_path_buf())));
+/// assert_eq!(Path::new("good.c").try_to_arg().unwrap(), "good.c");
+/// # }
+/// ```
+pub trait TryToArg {
+    /// Try to convert the type to an argument
+    fn try_to_arg(self) -> Result<String, Error>;
+}
+
+/// Try to convert a list of arguments
+pub(crate) fn try_to_args(
+    args: impl IntoIterator<Item = impl TryToArg>,
+) -> Result<Vec<String>, Error> {
+    args.into_iter().map(TryToArg::try_to_arg).collect()
+}
+
+/// Convert a list of paths to arguments, escaping them if `escape` is true.
+///
+/// Literals are always escaped.
+pub(crate) fn to_path_args(
+    args: impl IntoIterator<Item = impl ToArg>,
+    escape: bool,
+) -> impl Iterator<Item = String> {
+    args.into_iter().map(move |arg| {
+        if escape {
+            arg.to_path_arg()
+        } else {
+            arg.to_ninja_str().into_path()
+        }
+    })
 }
 
 /// Convert a mixed list of arguments types to a list of strings
@@ -125,6 +205,32 @@ macro_rules! impl_with {
                 self.as_os_str().to_arg()
             }
         }
+        impl TryToArg for $ty {
+            fn try_to_arg(self) -> Result<String, Error> {
+                self.as_os_str().try_to_arg()
+            }
+        }
+        )*
+    };
+
+    (try_unwrap for $($ty:ty),*) => {
+        $(
+        impl ToArg for $ty {
+            fn to_arg(self) -> String {
+                self.try_to_arg().unwrap()
+            }
+        }
+        )*
+    };
+
+    (infallible for $($ty:ty),*) => {
+        $(
+        impl TryToArg for $ty {
+            #[inline]
+            fn try_to_arg(self) -> Result<String, Error> {
+                Ok(self.to_arg())
+            }
+        }
         )*
     };
 }
@@ -193,52 +299,79 @@ mod impls {
     test_case!(bool, bool_true, true, "true");
     test_case!(bool, bool_false, false, "false");
 
+    impl_with!(infallible for
+        String, &str, &String,
+        i8, i16, i32, i64, i128, isize,
+        u8, u16, u32, u64, u128, usize,
+        bool
+    );
+
     #[cfg(feature = "std")]
     mod std_impls {
         use std::{ffi::{OsStr, OsString}, path::{Path, PathBuf}};
 
         use super::*;
 
-        impl ToArg for &[u8] {
-            fn to_arg(self) -> String {
-                String::from_utf8(self.to_vec()).unwrap()
+        impl TryToArg for &[u8] {
+            fn try_to_arg(self) -> Result<String, Error> {
+                self.to_vec().try_to_arg()
             }
         }
         test_case!(&[u8], u8_slice_ref, b"foo", "foo");
 
-        impl ToArg for Vec<u8> {
-            fn to_arg(self) -> String {
-                String::from_utf8(self).unwrap()
+        impl TryToArg for Vec<u8> {
+            fn try_to_arg(self) -> Result<String, Error> {
+                String::from_utf8(self).map_err(|e| Error::InvalidUtf8(e.into_bytes()))
             }
         }
         test_case!(Vec<u8>, u8_vec, vec![ 0xe4,0xbd,0xa0,0xe5,0xa5,0xbd ], "\u{4f60}\u{597d}");
 
-        impl ToArg for &Vec<u8> {
-            fn to_arg(self) -> String {
-                String::from_utf8(self.clone()).unwrap()
+        impl TryToArg for &Vec<u8> {
+            fn try_to_arg(self) -> Result<String, Error> {
+                self.clone().try_to_arg()
             }
         }
         test_case!(&Vec<u8>, u8_vec_ref, &vec![ 0xe4,0xbd,0xa0,0xe5,0xa5,0xbd ], "\u{4f60}\u{597d}");
 
-        impl ToArg for &OsStr {
-            fn to_arg(self) -> String {
-                self.to_str().unwrap().to_owned()
+        impl TryToArg for &OsStr {
+            fn try_to_arg(self) -> Result<String, Error> {
+                match self.to_str() {
+                    Some(s) => Ok(s.to_owned()),
+                    None => Err(Error::InvalidUtf8Path(self.into())),
+                }
             }
         }
         test_case!(&OsStr, os_str_ref, OsStr::new("foo"), "foo");
 
-        impl ToArg for OsString {
-            fn to_arg(self) -> String {
-                self.into_string().unwrap()
+        impl TryToArg for OsString {
+            fn try_to_arg(self) -> Result<String, Error> {
+                self.into_string().map_err(|s| Error::InvalidUtf8Path(s.into()))
             }
         }
         test_case!(OsString, os_string, OsString::from("foo"), "foo");
 
+        impl_with!(try_unwrap for &[u8], Vec<u8>, &Vec<u8>, &OsStr, OsString);
+
         impl_with!(as_os_str for &Path, &OsString, PathBuf, &PathBuf);
         test_case!(&OsString, os_string_ref, &OsString::from("foo"), "foo");
         test_case!(&Path, path_ref, Path::new("foo"), "foo");
         test_case!(PathBuf, path_buf, "/foo/".into(), "/foo/");
         test_case!(&PathBuf, path_buf_ref, &PathBuf::from("/foo/"), "/foo/");
+
+        #[test]
+        fn test_invalid_utf8() {
+            let bytes = vec![b'a', 0xff];
+            assert_eq!(bytes.clone().try_to_arg(), Err(Error::InvalidUtf8(bytes.clone())));
+            assert_eq!(bytes.as_slice().try_to_arg(), Err(Error::InvalidUtf8(bytes)));
+            #[cfg(unix)]
+            {
+                use std::os::unix::ffi::OsStringExt;
+                let name = OsString::from_vec(vec![b'a', 0xff]);
+                let error = Error::InvalidUtf8Path(name.clone().into());
+                assert_eq!(name.as_os_str().try_to_arg(), Err(error.clone()));
+                assert_eq!(PathBuf::from(name).try_to_arg(), Err(error));
+            }
+        }
     }
     
     
@@ -1,12 +1,17 @@
 //! Implementation of the `build` keyword
 
+use alloc::boxed::Box;
 use alloc::string::String;
-use core::fmt::{Display, Formatter, Result};
+use core::fmt::{Display, Formatter, Result, Write};
 use core::ops::Deref;
 
+use crate::arg::{to_path_args, try_to_args};
+use crate::eval::evaluate_list;
 use crate::stmt::{Stmt, StmtRef};
-use crate::util::{AddOnlyVec, Indented, RefCounted};
-use crate::{Rule, RuleVariables, ToArg, Variable, Variables};
+use crate::util::{Indented, RefCounted, SharedVec};
+use crate::{
+    Error, EvalError, Expanded, Ninja, Rule, RuleVariables, ToArg, TryToArg, Variable, Variables,
+};
 
 /// A build edge, as defined by the `build` keyword
 ///
@@ -35,35 +40,55 @@ pub struct Build {
     pub rule: RefCounted<String>,
 
     /// The list of outputs, as defined by `build <outputs>:`
-    pub outputs: AddOnlyVec<String>,
+    pub outputs: SharedVec<String>,
 
     /// The list of implicit outputs.
     ///
     /// See <https://ninja-build.org/manual.html#ref_outputs>
-    pub implicit_outputs: AddOnlyVec<String>,
+    pub implicit_outputs: SharedVec<String>,
 
     /// The list of dependencies (inputs).
     ///
     /// See <https://ninja-build.org/manual.html#ref_dependencies>
-    pub dependencies: AddOnlyVec<String>,
+    pub dependencies: SharedVec<String>,
 
     /// The list of implicit dependencies (inputs).
     ///
     /// See <https://ninja-build.org/manual.html#ref_dependencies>
-    pub implicit_dependencies: AddOnlyVec<String>,
+    pub implicit_dependencies: SharedVec<String>,
 
     /// The list of order-only dependencies (inputs).
     ///
     /// See <https://ninja-build.org/manual.html#ref_dependencies>
-    pub order_only_dependencies: AddOnlyVec<String>,
+    pub order_only_dependencies: SharedVec<String>,
 
     /// The list of validations.
     ///
     /// See <https://ninja-build.org/manual.html#validations>
-    pub validations: AddOnlyVec<String>,
+    pub validations: SharedVec<String>,
 
     /// The list of variables, as an indented block
-    pub variables: AddOnlyVec<Variable>,
+    pub variables: SharedVec<Variable>,
+
+    /// If paths added with the builder methods are escaped automatically
+    ///
+    /// This is copied from the rule when the build edge is created.
+    /// See [`Ninja::auto_escape`](crate::Ninja::auto_escape)
+    pub auto_escape: bool,
+}
+
+impl PartialEq for Build {
+    /// Compare the rendered parts of the build edges, but not [`auto_escape`](Self::auto_escape)
+    fn eq(&self, other: &Self) -> bool {
+        self.rule == other.rule
+            && self.outputs == other.outputs
+            && self.implicit_outputs == other.implicit_outputs
+            && self.dependencies == other.dependencies
+            && self.implicit_dependencies == other.implicit_dependencies
+            && self.order_only_dependencies == other.order_only_dependencies
+            && self.validations == other.validations
+            && self.variables == other.variables
+    }
 }
 
 /// Trait for implementing build-specific variables
@@ -110,12 +135,47 @@ pub trait BuildVariables: Variables {
     /// build foo: example bar baz
     /// "###);
     fn with(self, inputs: impl IntoIterator<Item = impl ToArg>) -> Self {
-        self.as_build()
+        let build = self.as_build();
+        build
             .dependencies
-            .extend(inputs.into_iter().map(|s| s.to_arg()));
+            .extend(to_path_args(inputs, build.auto_escape));
         self
     }
 
+    /// Add explicit dependencies (inputs), or return an error if any input can't be converted.
+    ///
+    /// Nothing is added if there is an error.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(all(feature = "std", unix))]
+    /// # {
+    /// use std::ffi::OsStr;
+    /// use std::os::unix::ffi::OsStrExt;
+    /// use std::path::Path;
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let rule = ninja.rule("example", "...");
+    /// let bad = Path::new(OsStr::from_bytes(b"bad\xff"));
+    /// let result = rule.build(["foo"]).try_with([Path::new("bar"), bad]);
+    /// assert_eq!(result.unwrap_err(), Error::InvalidUtf8Path(bad.to_path_buf()));
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule example
+    ///   command = ...
+    ///
+    /// build foo: example
+    /// "###);
+    /// # }
+    /// ```
+    fn try_with(
+        self,
+        inputs: impl IntoIterator<Item = impl TryToArg>,
+    ) -> core::result::Result<Self, Error> {
+        Ok(self.with(try_to_args(inputs)?))
+    }
+
     /// Add implicit dependencies
     ///
     /// See <https://ninja-build.org/manual.html#ref_dependencies>
@@ -136,9 +196,10 @@ pub trait BuildVariables: Variables {
     /// build foo: example bar baz | qux
     /// "###);
     fn with_implicit(self, inputs: impl IntoIterator<Item = impl ToArg>) -> Self {
-        self.as_build()
+        let build = self.as_build();
+        build
             .implicit_dependencies
-            .extend(inputs.into_iter().map(|s| s.to_arg()));
+            .extend(to_path_args(inputs, build.auto_escape));
         self
     }
 
@@ -163,9 +224,10 @@ pub trait BuildVariables: Variables {
     /// build foo: example bar baz | qux || oo
     /// "###);
     fn with_order_only(self, inputs: impl IntoIterator<Item = impl ToArg>) -> Self {
-        self.as_build()
+        let build = self.as_build();
+        build
             .order_only_dependencies
-            .extend(inputs.into_iter().map(|s| s.to_arg()));
+            .extend(to_path_args(inputs, build.auto_escape));
         self
     }
 
@@ -191,9 +253,10 @@ pub trait BuildVariables: Variables {
     /// build foo: example bar baz | qux || oo |@ quux
     /// "###);
     fn validations(self, validations: impl IntoIterator<Item = impl ToArg>) -> Self {
-        self.as_build()
+        let build = self.as_build();
+        build
             .validations
-            .extend(validations.into_iter().map(|s| s.to_arg()));
+            .extend(to_path_args(validations, build.auto_escape));
         self
     }
 
@@ -220,9 +283,70 @@ pub trait BuildVariables: Variables {
     /// build foo | iii: example bar baz | qux || oo |@ quux
     /// "###);
     fn output_implicit(self, outputs: impl IntoIterator<Item = impl ToArg>) -> Self {
-        self.as_build()
+        let build = self.as_build();
+        build
             .implicit_outputs
-            .extend(outputs.into_iter().map(|s| s.to_arg()));
+            .extend(to_path_args(outputs, build.auto_escape));
+        self
+    }
+
+    /// Remove an input from the explicit, implicit and order-only dependencies
+    ///
+    /// The input is escaped the same way as when it is added.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let rule = ninja.rule("example", "...");
+    /// rule.build(["foo"]).with(["bar", "baz"])
+    ///     .with_implicit(["bar"])
+    ///     .remove_input("bar");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule example
+    ///   command = ...
+    ///
+    /// build foo: example baz
+    /// "###);
+    /// ```
+    fn remove_input(self, input: impl ToArg) -> Self {
+        let build = self.as_build();
+        let input = to_path_args([input], build.auto_escape).next();
+        let keep = |path: &String| Some(path) != input.as_ref();
+        build.dependencies.retain(keep);
+        build.implicit_dependencies.retain(keep);
+        build.order_only_dependencies.retain(keep);
+        self
+    }
+
+    /// Remove a validation
+    ///
+    /// The path is escaped the same way as when it is added.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let rule = ninja.rule("example", "...");
+    /// rule.build(["foo"]).validations(["bar", "baz"])
+    ///     .remove_validation("bar");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule example
+    ///   command = ...
+    ///
+    /// build foo: example |@ baz
+    /// "###);
+    /// ```
+    fn remove_validation(self, validation: impl ToArg) -> Self {
+        let build = self.as_build();
+        let validation = to_path_args([validation], build.auto_escape).next();
+        build
+            .validations
+            .retain(|path| Some(path) != validation.as_ref());
         self
     }
 }
@@ -249,22 +373,73 @@ impl AsRef<Build> for BuildRef {
     }
 }
 
+impl BuildRef {
+    /// Expand the command, description, depfile and response file of this build edge,
+    /// with all the statements in the ninja file
+    ///
+    /// If the build edge is in a [section](crate::Ninja::section), the statements of
+    /// the ninja file the sections are in are used, the same as
+    /// [`Ninja::evaluate`](crate::Ninja::evaluate). A build edge that was
+    /// [removed](Self::remove) is expanded as if it was at the end of the ninja file.
+    ///
+    /// See [`Expanded`] for an example, and [`Evaluation`](crate::Evaluation) for
+    /// how variables are evaluated.
+    pub fn expand(&self) -> core::result::Result<Expanded, EvalError> {
+        let mut stmts = self.0.root.all_stmts();
+        if !stmts
+            .iter()
+            .any(|stmt| RefCounted::ptr_eq(stmt, &self.0.stmt))
+        {
+            stmts.push(RefCounted::clone(&self.0.stmt));
+        }
+        let eval = evaluate_list(&stmts);
+        match eval.edge(self) {
+            Some(edge) => edge.expand(),
+            None => Err(EvalError::EdgeNotFound),
+        }
+    }
+
+    /// Remove the build edge from the ninja file, and return if it was in the ninja file
+    ///
+    /// See [`StmtRef::remove`] for an example.
+    #[inline]
+    pub fn remove(&self) -> bool {
+        self.0.remove()
+    }
+}
+
 impl Build {
     /// Create a new build with the given explicit outputs and rule
+    ///
+    /// The outputs are escaped if auto-escaping is enabled on the rule.
+    /// A build without any outputs (explicit or implicit) is not rendered, since ninja
+    /// rejects it. [`Ninja::validate`](crate::Ninja::validate) reports it as
+    /// [`ValidationError::NoOutputs`](crate::ValidationError::NoOutputs).
     pub fn new(rule: &Rule, outputs: impl IntoIterator<Item = impl ToArg>) -> Self {
-        let self_outputs = AddOnlyVec::new();
-        self_outputs.extend(outputs.into_iter().map(|s| s.to_arg()));
+        let self_outputs = SharedVec::new();
+        self_outputs.extend(to_path_args(outputs, rule.auto_escape));
         Self {
             rule: RefCounted::clone(&rule.name),
             outputs: self_outputs,
-            implicit_outputs: AddOnlyVec::new(),
-            dependencies: AddOnlyVec::new(),
-            implicit_dependencies: AddOnlyVec::new(),
-            order_only_dependencies: AddOnlyVec::new(),
-            validations: AddOnlyVec::new(),
-            variables: AddOnlyVec::new(),
+            implicit_outputs: SharedVec::new(),
+            dependencies: SharedVec::new(),
+            implicit_dependencies: SharedVec::new(),
+            order_only_dependencies: SharedVec::new(),
+            validations: SharedVec::new(),
+            variables: SharedVec::new(),
+            auto_escape: rule.auto_escape,
         }
     }
+
+    /// Add the build edge to a ninja file, which can be a [section](Ninja::section)
+    /// other than the one the rule was added to
+    ///
+    /// # Example
+    /// See [`Ninja::section`]
+    #[inline]
+    pub fn add_to(self, ninja: &Ninja) -> BuildRef {
+        BuildRef(ninja.add_stmt(Stmt::Build(Box::new(self))))
+    }
 }
 
 impl Variables for Build {
@@ -272,6 +447,14 @@ impl Variables for Build {
     fn add_variable_internal(&self, v: Variable) {
         self.variables.add(v);
     }
+    fn set_variable_internal(&self, v: Variable) {
+        let name = v.name.clone();
+        self.variables.set(|x| x.name == name, v);
+    }
+
+    fn remove_variable_internal(&self, name: &str) {
+        self.variables.retain(|x| x.name != name);
+    }
 }
 
 impl BuildVariables for Build {
@@ -287,6 +470,15 @@ impl Variables for BuildRef {
     fn add_variable_internal(&self, v: Variable) {
         self.deref().variables.add(v);
     }
+    #[inline]
+    fn set_variable_internal(&self, v: Variable) {
+        self.deref().set_variable_internal(v);
+    }
+
+    #[inline]
+    fn remove_variable_internal(&self, name: &str) {
+        self.deref().remove_variable_internal(name);
+    }
 }
 
 impl BuildVariables for BuildRef {
@@ -297,8 +489,9 @@ impl BuildVariables for BuildRef {
 
 impl RuleVariables for BuildRef {}
 
-impl Display for Build {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+impl Build {
+    /// Write the `build` line, without the newline
+    pub(crate) fn write_header(&self, f: &mut impl Write) -> Result {
         write!(f, "build")?;
         for output in self.outputs.inner().iter() {
             write!(f, " {}", output)?;
@@ -343,6 +536,13 @@ impl Display for Build {
                 }
             }
         }
+        Ok(())
+    }
+}
+
+impl Display for Build {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.write_header(f)?;
         writeln!(f)?;
         for variable in self.variables.inner().iter() {
             Indented(variable).fmt(f)?;
@@ -0,0 +1,496 @@
+//! Byte strings for paths that are not valid UTF-8
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use core::fmt::Write;
+
+use crate::parse::parse_into;
+use crate::{Ninja, NinjaStr, ParseError, RenderOptions, ToArg, escape_build};
+
+/// The character that byte `0x80` is stored as, after a `$`. Bytes up to `0xFF` follow it.
+const BYTE_BASE: u32 = 0x10FF00;
+
+/// Wrapper for an argument made of bytes, which don't need to be valid UTF-8
+///
+/// The statements are stored as strings, so each byte that is not valid UTF-8 is stored as
+/// a `$` followed by a character in the range `U+10FF80` to `U+10FFFF` (at the end of
+/// Supplementary Private Use Area-B). Ninja doesn't allow a `$` before these characters, so
+/// the pair can't come from escaped text. [`Ninja::write_bytes`] and [`Ninja::to_bytes`]
+/// turn the pairs back into the original bytes, while [`Display`](core::fmt::Display),
+/// [`Ninja::evaluate`] and the other text outputs of the whole file show them as `U+FFFD`.
+/// The `Display` of a single statement (such as [`Stmt`](crate::Stmt), [`Build`](crate::Build)
+/// or a node of a [`Cst`](crate::Cst)) doesn't support bytes, and writes the stored pairs.
+///
+/// Like strings, the bytes are taken as escaped text, unless auto-escaping is enabled
+/// (see [`Ninja::auto_escape`]). Wrapped in [`Literal`](crate::Literal) or
+/// [`NinjaStr::literal`], or given to [`ShellCommand::arg`](crate::ShellCommand::arg), the
+/// valid text is escaped and the other bytes are kept as they are. Comments are not escaped, so a comment added as a string
+/// with `$` followed by a character in that range is written as the byte as well.
+///
+/// On Unix, `OsStr`, `OsString`, `Path` and `PathBuf` can be wrapped as well.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(all(feature = "std", unix))]
+/// # {
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use std::path::Path;
+///
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// let cp = ninja.rule("cp", "cp $in $out");
+/// let source = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+/// cp.build(["out.txt"]).with([Bytes(source)]);
+///
+/// let mut output = Vec::new();
+/// ninja.write_bytes(&mut output).unwrap();
+/// assert_eq!(output, b"
+/// rule cp
+///   command = cp $in $out
+///
+/// build out.txt: cp caf\xe9.txt
+/// ");
+/// assert!(ninja.to_string().ends_with("build out.txt: cp caf\u{fffd}.txt\n"));
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bytes<T>(pub T);
+
+macro_rules! impl_bytes {
+    ($($ty:ty => |$bytes:ident| $slice:expr),* $(,)?) => {
+        $(
+        impl ToArg for Bytes<$ty> {
+            #[inline]
+            fn to_arg(self) -> String {
+                let $bytes = self.0;
+                encode_bytes($slice).into_owned()
+            }
+
+            #[inline]
+            fn to_path_arg(self) -> String {
+                let $bytes = self.0;
+                encode_path_bytes($slice)
+            }
+
+            #[inline]
+            fn to_ninja_str(self) -> NinjaStr {
+                let $bytes = self.0;
+                bytes_to_ninja_str($slice)
+            }
+        }
+        )*
+    };
+}
+
+impl_bytes!(
+    &[u8] => |bytes| bytes,
+    Vec<u8> => |bytes| &bytes,
+    &Vec<u8> => |bytes| bytes,
+);
+
+#[cfg(all(feature = "std", unix))]
+mod unix_impls {
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    impl_bytes!(
+        &OsStr => |s| s.as_bytes(),
+        OsString => |s| s.as_bytes(),
+        &OsString => |s| s.as_bytes(),
+        &Path => |path| path.as_os_str().as_bytes(),
+        PathBuf => |path| path.as_os_str().as_bytes(),
+        &PathBuf => |path| path.as_os_str().as_bytes(),
+    );
+}
+
+/// Get the byte that is stored as the character after a `$`, if it is one
+#[inline]
+pub(crate) fn stored_byte(c: char) -> Option<u8> {
+    let c = c as u32;
+    (c >= BYTE_BASE + 0x80).then(|| (c - BYTE_BASE) as u8)
+}
+
+/// Encode the bytes as a string, storing bytes that are not valid UTF-8 as `$` and
+/// a reserved character
+pub(crate) fn encode_bytes(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(s) = core::str::from_utf8(bytes) {
+        return Cow::Borrowed(s);
+    }
+    let mut output = String::with_capacity(bytes.len() + 8);
+    for chunk in bytes.utf8_chunks() {
+        output.push_str(chunk.valid());
+        push_invalid(&mut output, chunk.invalid());
+    }
+    Cow::Owned(output)
+}
+
+/// Split the bytes into raw text and [`Segment::Bytes`] for the bytes that are not valid UTF-8
+fn bytes_to_ninja_str(bytes: &[u8]) -> NinjaStr {
+    let mut output = NinjaStr::new();
+    for chunk in bytes.utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            output = output.raw(chunk.valid());
+        }
+        if !chunk.invalid().is_empty() {
+            output = output.bytes(chunk.invalid().to_vec());
+        }
+    }
+    output
+}
+
+/// Encode the bytes like [`encode_bytes`], escaping the valid text with [`escape_build`]
+fn encode_path_bytes(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len() + 8);
+    for chunk in bytes.utf8_chunks() {
+        output.push_str(&escape_build(chunk.valid()));
+        push_invalid(&mut output, chunk.invalid());
+    }
+    output
+}
+
+fn push_invalid(output: &mut String, invalid: &[u8]) {
+    for byte in invalid {
+        output.push('$');
+        // safety: the invalid bytes are at least 0x80, so the character is in
+        // U+10FF80..=U+10FFFF
+        output.push(char::from_u32(BYTE_BASE + *byte as u32).unwrap());
+    }
+}
+
+/// Decode a whole string from [`encode_bytes`] back to the bytes
+pub(crate) fn decode_bytes(s: &str) -> Cow<'_, [u8]> {
+    if !s.contains(|c| stored_byte(c).is_some()) {
+        return Cow::Borrowed(s.as_bytes());
+    }
+    let mut output = Vec::with_capacity(s.len());
+    let mut decoder = ByteDecoder::default();
+    let mut push = |part: Part| {
+        match part {
+            Part::Text(text) => output.extend_from_slice(text.as_bytes()),
+            Part::Byte(byte) => output.push(byte),
+        }
+        Ok::<_, ()>(())
+    };
+    let _ = decoder.decode(s, &mut push);
+    let _ = decoder.finish(&mut push);
+    Cow::Owned(output)
+}
+
+/// A part of the text decoded by [`ByteDecoder`]
+pub(crate) enum Part<'a> {
+    /// Text that is written as is
+    Text(&'a str),
+    /// A byte that was stored with [`encode_bytes`]
+    Byte(u8),
+}
+
+/// Decoder for the bytes stored with [`encode_bytes`], where the text comes in chunks
+///
+/// A `$` is only decoded together with the character after it, so `$$` followed by
+/// a reserved character is left as is.
+#[derive(Debug, Default)]
+pub(crate) struct ByteDecoder {
+    /// If the last chunk ended with a `$` that is not written yet
+    dollar: bool,
+}
+
+impl ByteDecoder {
+    /// Decode the next chunk, calling `f` with the parts in order
+    pub fn decode<'a, E>(
+        &mut self,
+        text: &'a str,
+        f: &mut impl FnMut(Part<'a>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut rest = text;
+        if self.dollar && !rest.is_empty() {
+            self.dollar = false;
+            rest = self.after_dollar(rest, f)?;
+        }
+        while let Some(i) = rest.find('$') {
+            if i > 0 {
+                f(Part::Text(&rest[..i]))?;
+            }
+            let after = &rest[i + 1..];
+            if after.is_empty() {
+                self.dollar = true;
+                return Ok(());
+            }
+            rest = self.after_dollar(after, f)?;
+        }
+        if !rest.is_empty() {
+            f(Part::Text(rest))?;
+        }
+        Ok(())
+    }
+
+    /// Write the `$` at the end of the last chunk, if any
+    pub fn finish<'a, E>(
+        &mut self,
+        f: &mut impl FnMut(Part<'a>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if core::mem::take(&mut self.dollar) {
+            f(Part::Text("$"))?;
+        }
+        Ok(())
+    }
+
+    /// Decode the character after a `$`, returning the rest of the text
+    fn after_dollar<'a, E>(
+        &mut self,
+        after: &'a str,
+        f: &mut impl FnMut(Part<'a>) -> Result<(), E>,
+    ) -> Result<&'a str, E> {
+        // safety: the text is not empty
+        let c = after.chars().next().unwrap();
+        if let Some(byte) = stored_byte(c) {
+            f(Part::Byte(byte))?;
+            return Ok(&after[c.len_utf8()..]);
+        }
+        if c == '$' {
+            f(Part::Text("$$"))?;
+            return Ok(&after[1..]);
+        }
+        f(Part::Text("$"))?;
+        Ok(after)
+    }
+}
+
+/// Adapter for writing text with the bytes stored with [`encode_bytes`] shown as `U+FFFD`
+pub(crate) struct LossyWriter<'w, W: Write> {
+    pub inner: &'w mut W,
+    decoder: ByteDecoder,
+}
+
+impl<'w, W: Write> LossyWriter<'w, W> {
+    #[inline]
+    pub fn new(inner: &'w mut W) -> Self {
+        Self {
+            inner,
+            decoder: ByteDecoder::default(),
+        }
+    }
+
+    /// Write the text that is held back by the decoder
+    pub fn finish(mut self) -> core::fmt::Result {
+        let inner = &mut *self.inner;
+        self.decoder.finish(&mut |part| write_lossy(inner, part))
+    }
+}
+
+impl<W: Write> Write for LossyWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let inner = &mut *self.inner;
+        self.decoder.decode(s, &mut |part| write_lossy(inner, part))
+    }
+}
+
+fn write_lossy(writer: &mut impl Write, part: Part) -> core::fmt::Result {
+    match part {
+        Part::Text(text) => writer.write_str(text),
+        Part::Byte(_) => writer.write_char(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+impl Ninja {
+    /// Parse an existing ninja file from bytes, which don't need to be valid UTF-8
+    ///
+    /// Bytes that are not valid UTF-8 are stored the same way as [`Bytes`], so
+    /// [`write_bytes`](Self::write_bytes) writes them back exactly.
+    /// See [`parse`](Self::parse) for details.
+    ///
+    /// A comment with `$` right before such a byte is an error, since it can't be
+    /// written back exactly.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use ninja_writer::*;
+    ///
+    /// let input = b"build caf\xe9.o: phony\n";
+    /// let ninja = Ninja::parse_bytes(input).unwrap();
+    ///
+    /// let mut output = Vec::new();
+    /// ninja.write_bytes(&mut output).unwrap();
+    /// assert_eq!(output, b"\nbuild caf\xe9.o: phony\n");
+    /// # }
+    /// ```
+    pub fn parse_bytes(input: &[u8]) -> Result<Self, ParseError> {
+        let ninja = Self::new();
+        // the positions of the stored bytes, so `$` followed by a reserved character in the
+        // input is still a bad escape
+        let mut encoded = String::with_capacity(input.len());
+        let mut tags = Vec::new();
+        for chunk in input.utf8_chunks() {
+            encoded.push_str(chunk.valid());
+            for byte in chunk.invalid() {
+                tags.push(encoded.len());
+                push_invalid(&mut encoded, &[*byte]);
+            }
+        }
+        parse_into(&ninja, &encoded, &tags)?;
+        Ok(ninja)
+    }
+
+    /// Render the ninja file as bytes, turning arguments made with [`Bytes`] back into
+    /// the original bytes
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.defaults([Bytes(&b"caf\xe9"[..])]);
+    /// assert_eq!(ninja.to_bytes(), b"\ndefault caf\xe9\n");
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = String::new();
+        // writing to a string doesn't fail
+        let _ = self.render_stored(&mut output, &RenderOptions::default());
+        match decode_bytes(&output) {
+            Cow::Owned(bytes) => bytes,
+            Cow::Borrowed(_) => output.into_bytes(),
+        }
+    }
+
+    /// Write the ninja file as bytes, turning arguments made with [`Bytes`] back into
+    /// the original bytes
+    ///
+    /// Like [`write_to`](Self::write_to), the file is written statement by statement.
+    /// See [`Bytes`] for an example.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn write_bytes(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        self.write_io(writer, true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, Literal, ParseErrorKind, Shell, ShellCommand};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_round_trip() {
+        let inputs: [&[u8]; 5] = [
+            b"",
+            b"abc",
+            b"\xff\xfe",
+            b"caf\xc3\xa9 caf\xe9 \xe9\x80",
+            b"\xf0\x9f\x98\x80\xf0\x9f\x98",
+        ];
+        for input in inputs {
+            let encoded = encode_bytes(input);
+            assert_eq!(decode_bytes(&encoded).as_ref(), input);
+        }
+        assert!(matches!(encode_bytes(b"abc"), Cow::Borrowed(_)));
+        assert!(matches!(decode_bytes("abc"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_decode_text() {
+        // only `$` followed by a reserved character is a byte
+        assert_eq!(
+            decode_bytes("a\u{10ffab}b").as_ref(),
+            "a\u{10ffab}b".as_bytes()
+        );
+        assert_eq!(
+            decode_bytes("$$\u{10ffab}").as_ref(),
+            "$$\u{10ffab}".as_bytes()
+        );
+        assert_eq!(decode_bytes("$$$\u{10ffab}$").as_ref(), b"$$\xab$");
+
+        // `$` at the end of a chunk
+        let mut output = Vec::new();
+        let mut decoder = ByteDecoder::default();
+        let mut push = |part: Part| {
+            match part {
+                Part::Text(text) => output.extend_from_slice(text.as_bytes()),
+                Part::Byte(byte) => output.push(byte),
+            }
+            Ok::<_, ()>(())
+        };
+        for chunk in ["a$", "\u{10ffab}$", "$\u{10ffab}", "$"] {
+            decoder.decode(chunk, &mut push).unwrap();
+        }
+        decoder.finish(&mut push).unwrap();
+        let expected = [b"a\xab".as_slice(), "$$\u{10ffab}$".as_bytes()].concat();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_bytes_arg() {
+        assert_eq!(Bytes(&b"abc"[..]).to_arg(), "abc");
+        assert_eq!(Bytes(b"a\xffb".to_vec()).to_arg(), "a$\u{10ffff}b");
+        assert_eq!(Bytes(&b"\x80".to_vec()).to_arg(), "$\u{10ff80}");
+        assert_eq!(Bytes(&b"a b:\xff"[..]).to_path_arg(), "a$ b$:$\u{10ffff}");
+    }
+
+    #[test]
+    fn test_text_outputs() {
+        let ninja = Ninja::new().auto_escape();
+        ninja.comment("U+10FFAB: \u{10ffab}");
+        ninja.variable("x", Bytes(&b"caf\xe9"[..]));
+        ninja.phony([Bytes(&b"a \xff"[..])]).with(["\u{10ffab}"]);
+        let expected = [
+            "\n# U+10FFAB: \u{10ffab}\n\nx = caf".as_bytes(),
+            b"\xe9\n\nbuild a$ \xff: phony ",
+            "\u{10ffab}\n".as_bytes(),
+        ]
+        .concat();
+        assert_eq!(ninja.to_bytes(), expected);
+        assert_eq!(
+            ninja.to_string(),
+            "\n# U+10FFAB: \u{10ffab}\n\nx = caf\u{fffd}\n\nbuild a$ \u{fffd}: phony \u{10ffab}\n"
+        );
+        let eval = ninja.evaluate();
+        assert_eq!(eval.variable("x"), Some("caf\u{fffd}"));
+        assert_eq!(eval.edges[0].outputs, ["a \u{fffd}"]);
+        assert_eq!(eval.edges[0].dependencies, ["\u{10ffab}"]);
+
+        // parsing keeps the bytes, but only allows them from bytes
+        let parsed = Ninja::parse_bytes(&ninja.to_bytes()).unwrap();
+        assert_eq!(parsed, ninja);
+        assert_eq!(parsed.to_bytes(), ninja.to_bytes());
+        assert!(Ninja::parse("x = $\u{10ffab}\n").is_err());
+        assert!(Ninja::parse("# $\u{10ffab}\n").is_err());
+
+        // only the bytes themselves, not the text they are stored as
+        let err = Ninja::parse_bytes("x = $\u{10ffff}\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::BadEscape);
+        assert!(Ninja::parse_bytes("# $\u{10ffff}\n".as_bytes()).is_err());
+        assert!(Ninja::parse_bytes(b"# $\xff\n").is_err());
+        let parsed = Ninja::parse_bytes(b"# \xff $$\xff\nx = $$\xff\n").unwrap();
+        assert_eq!(parsed.to_bytes(), b"\n# \xff $$\xff\n\nx = $$\xff\n");
+    }
+
+    #[test]
+    fn test_literal_bytes() {
+        let ninja = Ninja::new();
+        ninja.variable("a", Literal(Bytes(&b"x\xffy $"[..])));
+        ninja.variable("b", NinjaStr::new().literal(Bytes(&b"\xff$"[..])).var("c"));
+        assert_eq!(ninja.to_bytes(), b"\na = x\xffy $$\nb = \xff$$$c\n");
+        assert_eq!(ninja.to_string(), "\na = x\u{fffd}y $$\nb = \u{fffd}$$$c\n");
+    }
+
+    #[test]
+    fn test_shell_bytes() {
+        let ninja = Ninja::new();
+        let command = ShellCommand::new(Shell::Posix, "echo").arg(Bytes(&b"x\xffy"[..]));
+        ninja.rule("echo", command);
+        let command = ShellCommand::new(Shell::Windows, "echo").arg(Bytes(&b"a b\xff"[..]));
+        ninja.rule("echo_windows", command);
+        assert_eq!(
+            ninja.to_bytes(),
+            b"\nrule echo\n  command = echo x'\xff'y\n\nrule echo_windows\n  command = echo \"a b\"\"\xff\"\n"
+        );
+    }
+}
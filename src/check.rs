@@ -0,0 +1,414 @@
+//! Diagnostics for common mistakes in a ninja file
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::eval::{evaluate, evaluate_list};
+use crate::stmt::{Stmt, StmtRef};
+use crate::util::RefCounted;
+use crate::validate::validate_stmts;
+use crate::{Graph, Ninja, ValidationError};
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Ninja accepts the file, but it is probably not what was meant
+    Warning,
+    /// Ninja refuses to load or build the file
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What a [`Diagnostic`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticCode {
+    /// An output is produced by more than one build edge (error).
+    /// See [`Graph::duplicate_outputs`](crate::Graph::duplicate_outputs)
+    DuplicateOutput,
+    /// The dependencies have a cycle (error).
+    /// See [`Graph::cycles`](crate::Graph::cycles)
+    DependencyCycle,
+    /// A build edge uses a rule that is not declared (error)
+    UndefinedRule,
+    /// A build edge uses a rule that is declared after it (error)
+    RuleNotYetDeclared,
+    /// A build edge uses a pool that is not declared (error)
+    UndefinedPool,
+    /// A build edge uses a pool that is declared after it (error)
+    PoolNotYetDeclared,
+    /// A build edge has no outputs, so it is not rendered (error)
+    NoOutputs,
+    /// A default target is not produced by any build edge (warning)
+    DefaultNotProduced,
+    /// A rule is not used by any build edge (warning)
+    UnusedRule,
+    /// A pool is not used by any build edge (warning)
+    UnusedPool,
+    /// A top-level variable is never referenced (warning)
+    UnusedVariable,
+    /// The command of a rule uses neither `$in` nor `$out` (warning)
+    CommandWithoutInOut,
+    /// `generator` or `restat` is set on a phony edge, where it has no effect (warning)
+    PhonyVariable,
+}
+
+impl DiagnosticCode {
+    /// Get the code as a kebab-case string, like `duplicate-output`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DuplicateOutput => "duplicate-output",
+            Self::DependencyCycle => "dependency-cycle",
+            Self::UndefinedRule => "undefined-rule",
+            Self::RuleNotYetDeclared => "rule-not-yet-declared",
+            Self::UndefinedPool => "undefined-pool",
+            Self::PoolNotYetDeclared => "pool-not-yet-declared",
+            Self::NoOutputs => "no-outputs",
+            Self::DefaultNotProduced => "default-not-produced",
+            Self::UnusedRule => "unused-rule",
+            Self::UnusedPool => "unused-pool",
+            Self::UnusedVariable => "unused-variable",
+            Self::CommandWithoutInOut => "command-without-in-out",
+            Self::PhonyVariable => "phony-variable",
+        }
+    }
+
+    /// Get the severity of diagnostics with this code
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::DuplicateOutput
+            | Self::DependencyCycle
+            | Self::UndefinedRule
+            | Self::RuleNotYetDeclared
+            | Self::UndefinedPool
+            | Self::PoolNotYetDeclared
+            | Self::NoOutputs => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem found by [`Ninja::check`]
+///
+/// The [`Display`] implementation formats it like `error[undefined-rule]: unknown build rule 'cc'`
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// How serious the problem is
+    pub severity: Severity,
+    /// What the problem is about
+    pub code: DiagnosticCode,
+    /// The human-readable description
+    pub message: String,
+    /// Index of the statement in the statements of the ninja file followed by the ones
+    /// in its [sections](Ninja::section), in the rendered order. Without sections, this
+    /// is the index in [`Ninja::stmts`].
+    pub index: usize,
+    /// The statement the problem is in
+    pub stmt: StmtRef,
+}
+
+impl Diagnostic {
+    /// Check if the diagnostic is an error
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// Top-level variables that ninja itself reads
+const SPECIAL_VARIABLES: [&str; 2] = ["builddir", "ninja_required_version"];
+
+impl Ninja {
+    /// Check the ninja file for errors that ninja would report and for likely mistakes.
+    ///
+    /// The errors are the ones from [`validate`](Self::validate),
+    /// [`Graph::duplicate_outputs`](crate::Graph::duplicate_outputs) and
+    /// [`Graph::cycles`](crate::Graph::cycles). See [`DiagnosticCode`] for the warnings.
+    /// The diagnostics are in the order of the statements.
+    /// `include` and `subninja` statements are not followed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.variable("cflags", "-O2");
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// ninja.rule("touch", "touch stamp");
+    /// cc.build(["foo.o"]).with(["foo.c"]);
+    /// cc.build(["foo.o"]).with(["bar.c"]);
+    /// ninja.defaults(["all"]);
+    ///
+    /// let diagnostics: Vec<_> = ninja.check().iter().map(|d| d.to_string()).collect();
+    /// assert_eq!(diagnostics, [
+    ///     "warning[unused-variable]: variable 'cflags' is never referenced",
+    ///     "warning[unused-rule]: rule 'touch' is not used by any build edge",
+    ///     "warning[command-without-in-out]: command of rule 'touch' uses neither $in nor $out",
+    ///     "error[duplicate-output]: multiple rules generate foo.o (cc at statement 3, cc at statement 4)",
+    ///     "warning[default-not-produced]: default target 'all' is not produced by any build edge",
+    /// ]);
+    ///
+    /// // fail if there are errors
+    /// assert!(ninja.check().iter().any(Diagnostic::is_error));
+    /// ```
+    pub fn check(&self) -> Vec<Diagnostic> {
+        // the statements, with the list of the section each one is in
+        let lists = self.stmt_lists();
+        let mut stmts = Vec::new();
+        let mut owners = Vec::new();
+        for list in lists.iter() {
+            for stmt in list.inner().iter() {
+                stmts.push(RefCounted::clone(stmt));
+                owners.push(list);
+            }
+        }
+        // evaluated once, for both the graph and the validation
+        let graph = Graph::new(evaluate_list(&stmts));
+        let mut diagnostics = Vec::new();
+        let mut add = |code: DiagnosticCode, index: usize, message: String| {
+            diagnostics.push(Diagnostic {
+                severity: code.severity(),
+                code,
+                message,
+                index,
+                stmt: StmtRef {
+                    list: RefCounted::clone(owners[index]),
+                    stmt: RefCounted::clone(&stmts[index]),
+                    root: self.root(),
+                },
+            });
+        };
+
+        if let Err(errors) = validate_stmts(&stmts, &graph.evaluation) {
+            for error in errors {
+                let code = match error {
+                    ValidationError::UndefinedRule { .. } => DiagnosticCode::UndefinedRule,
+                    ValidationError::RuleNotYetDeclared { .. } => {
+                        DiagnosticCode::RuleNotYetDeclared
+                    }
+                    ValidationError::UndefinedPool { .. } => DiagnosticCode::UndefinedPool,
+                    ValidationError::PoolNotYetDeclared { .. } => {
+                        DiagnosticCode::PoolNotYetDeclared
+                    }
+                    ValidationError::NoOutputs { .. } => DiagnosticCode::NoOutputs,
+                };
+                let mut message = String::new();
+                let _ = error.fmt_message(&mut message);
+                add(code, error.stmt(), message);
+            }
+        }
+        for duplicate in graph.duplicate_outputs() {
+            let index = duplicate.second_stmt;
+            add(
+                DiagnosticCode::DuplicateOutput,
+                index,
+                duplicate.to_string(),
+            );
+        }
+        for cycle in graph.cycles() {
+            // safety: the paths in a cycle are all produced by an edge
+            let (edge, _) = graph.producer(&cycle.path[0]).unwrap();
+            add(
+                DiagnosticCode::DependencyCycle,
+                edge.index,
+                cycle.to_string(),
+            );
+        }
+
+        // names of the rules and pools that are used and the variables that are referenced
+        let mut used_rules = BTreeSet::new();
+        let mut used_pools = BTreeSet::new();
+        let mut references = BTreeSet::new();
+        for edge in graph.evaluation.edges() {
+            if let Ok(pool) = edge.variable("pool") {
+                used_pools.insert(pool);
+            }
+        }
+        for stmt in stmts.iter() {
+            if let Stmt::Build(build) = stmt.as_ref() {
+                used_rules.insert(build.rule.as_str());
+            }
+            stmt_references(stmt, &mut references);
+        }
+
+        let mut defaults = graph.evaluation.defaults.iter();
+        for (index, stmt) in stmts.iter().enumerate() {
+            match stmt.as_ref() {
+                Stmt::Variable(variable)
+                    if !references.contains(variable.name.as_str())
+                        && !SPECIAL_VARIABLES.contains(&variable.name.as_str()) =>
+                {
+                    let message = format!("variable '{}' is never referenced", variable.name);
+                    add(DiagnosticCode::UnusedVariable, index, message);
+                }
+                Stmt::Rule(rule) => {
+                    if !used_rules.contains(rule.name.as_str()) {
+                        let message = format!("rule '{}' is not used by any build edge", rule.name);
+                        add(DiagnosticCode::UnusedRule, index, message);
+                    }
+                    let mut names = BTreeSet::new();
+                    for variable in rule.variables.inner().iter() {
+                        if variable.name == "command" {
+                            collect_references(&variable.value.escaped(), &mut names);
+                        }
+                    }
+                    if !["in", "in_newline", "out"]
+                        .iter()
+                        .any(|n| names.contains(*n))
+                    {
+                        let message =
+                            format!("command of rule '{}' uses neither $in nor $out", rule.name);
+                        add(DiagnosticCode::CommandWithoutInOut, index, message);
+                    }
+                }
+                Stmt::Pool(pool) if !used_pools.contains(&pool.name) => {
+                    let message = format!("pool '{}' is not used by any build edge", pool.name);
+                    add(DiagnosticCode::UnusedPool, index, message);
+                }
+                Stmt::Build(build) if build.rule.as_str() == "phony" => {
+                    for variable in build.variables.inner().iter() {
+                        if variable.name == "generator" || variable.name == "restat" {
+                            let message =
+                                format!("'{}' has no effect on phony edge", variable.name);
+                            add(DiagnosticCode::PhonyVariable, index, message);
+                        }
+                    }
+                }
+                Stmt::Default(outputs) => {
+                    // the evaluated defaults are in the same order as the statements
+                    for output in defaults.by_ref().take(outputs.len()) {
+                        if graph.producer(output).is_none() {
+                            let message = format!(
+                                "default target '{}' is not produced by any build edge",
+                                output
+                            );
+                            add(DiagnosticCode::DefaultNotProduced, index, message);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        diagnostics.sort_by_key(|d| d.index);
+        diagnostics
+    }
+}
+
+/// Collect the names of the variables referenced in the statement
+fn stmt_references(stmt: &Stmt, names: &mut BTreeSet<String>) {
+    match stmt {
+        Stmt::Comment(_) => {}
+        Stmt::Rule(rule) => {
+            for variable in rule.variables.inner().iter() {
+                collect_references(&variable.value.escaped(), names);
+            }
+        }
+        Stmt::Build(build) => {
+            for list in [
+                &build.outputs,
+                &build.implicit_outputs,
+                &build.dependencies,
+                &build.implicit_dependencies,
+                &build.order_only_dependencies,
+                &build.validations,
+            ] {
+                for path in list.inner().iter() {
+                    collect_references(path, names);
+                }
+            }
+            for variable in build.variables.inner().iter() {
+                collect_references(&variable.value.escaped(), names);
+            }
+        }
+        Stmt::Variable(variable) => collect_references(&variable.value.escaped(), names),
+        Stmt::Default(outputs) => {
+            for output in outputs {
+                collect_references(output, names);
+            }
+        }
+        Stmt::Subninja(path) | Stmt::Include(path) => collect_references(path, names),
+        Stmt::Pool(pool) => {
+            for variable in pool.variables.inner().iter() {
+                collect_references(&variable.value.escaped(), names);
+            }
+        }
+    }
+}
+
+/// Collect the names of the variables referenced in an escaped ninja string
+fn collect_references(text: &str, names: &mut BTreeSet<String>) {
+    let _ = evaluate(text, |name| {
+        names.insert(name.into());
+        Ok(String::new())
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, RuleVariables, Variables};
+
+    #[test]
+    fn test_clean() {
+        let ninja = Ninja::new();
+        ninja.variable("builddir", "out");
+        ninja.variable("cflags", "-O2");
+        let pool = ninja.pool("heavy", 1);
+        let cc = ninja.rule("cc", "gcc $cflags -c ${in} -o $out").pool(&pool);
+        cc.build(["foo.o"]).with(["foo.c"]);
+        ninja.phony(["all"]).with(["foo.o"]);
+        ninja.defaults(["all", "foo.c"]);
+        let diagnostics: Vec<_> = ninja.check().iter().map(|d| d.code).collect();
+        assert_eq!(diagnostics, [DiagnosticCode::DefaultNotProduced]);
+    }
+
+    #[test]
+    fn test_codes() {
+        let ninja = Ninja::new();
+        ninja.pool("unused", 1);
+        let cp = ninja.rule("cp", "cp $in_newline $out.tmp");
+        cp.build(["a"]).with(["b"]);
+        cp.build(["b"]).with(["a"]).variable("pool", "missing");
+        ninja.phony(["c"]).generator().restat();
+        let diagnostics: Vec<_> = ninja
+            .check()
+            .iter()
+            .map(|d| (d.index, d.code, d.severity))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                (0, DiagnosticCode::UnusedPool, Severity::Warning),
+                (2, DiagnosticCode::DependencyCycle, Severity::Error),
+                (3, DiagnosticCode::UndefinedPool, Severity::Error),
+                (4, DiagnosticCode::PhonyVariable, Severity::Warning),
+                (4, DiagnosticCode::PhonyVariable, Severity::Warning),
+            ]
+        );
+        let stmt = &ninja.check()[0].stmt;
+        assert!(matches!(stmt.as_ref(), Stmt::Pool(_)));
+    }
+}
@@ -0,0 +1,200 @@
+//! Export of clang's compilation database (`compile_commands.json`)
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+use crate::{EvalError, Ninja, ToArg};
+
+/// An entry in a [compilation database](https://clang.llvm.org/docs/JSONCompilationDatabase.html)
+///
+/// Use [`Ninja::compile_commands`] to create the entries and
+/// [`Ninja::compile_commands_json`] to create the `compile_commands.json` file.
+/// The [`Display`] implementation formats the entry as a JSON object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileCommand {
+    /// The working directory of the command
+    pub directory: String,
+    /// The expanded command
+    pub command: String,
+    /// The source file, which is the first explicit input of the build edge
+    pub file: String,
+    /// The output, which is the first explicit output of the build edge
+    pub output: String,
+}
+
+impl Display for CompileCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "{{")?;
+        write!(f, "    \"directory\": ")?;
+        write_json_str(f, &self.directory)?;
+        write!(f, ",\n    \"command\": ")?;
+        write_json_str(f, &self.command)?;
+        write!(f, ",\n    \"file\": ")?;
+        write_json_str(f, &self.file)?;
+        write!(f, ",\n    \"output\": ")?;
+        write_json_str(f, &self.output)?;
+        write!(f, "\n  }}")
+    }
+}
+
+impl Ninja {
+    /// Create the compilation database entries for the build edges that use the given rules,
+    /// similar to `ninja -t compdb`
+    ///
+    /// The directory is the working directory of the commands, normally where
+    /// the ninja file is. Build edges without explicit inputs are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc $cflags -c $in -o $out");
+    /// let ld = ninja.rule("ld", "gcc $in -o $out");
+    /// cc.build(["foo.o"]).with(["foo.c"]).variable("cflags", "-O2");
+    /// ld.build(["app"]).with(["foo.o"]);
+    ///
+    /// let commands = ninja.compile_commands("/src", ["cc"]).unwrap();
+    /// assert_eq!(commands, [CompileCommand {
+    ///     directory: "/src".to_string(),
+    ///     command: "gcc -O2 -c foo.c -o foo.o".to_string(),
+    ///     file: "foo.c".to_string(),
+    ///     output: "foo.o".to_string(),
+    /// }]);
+    /// ```
+    pub fn compile_commands(
+        &self,
+        directory: impl ToArg,
+        rules: impl IntoIterator<Item = impl ToArg>,
+    ) -> Result<Vec<CompileCommand>, EvalError> {
+        let directory = directory.to_arg();
+        let rules: Vec<String> = rules.into_iter().map(|r| r.to_arg()).collect();
+        let eval = self.evaluate();
+        let mut commands = Vec::new();
+        for edge in eval.edges() {
+            let Some(rule) = edge.rule() else {
+                continue;
+            };
+            if !rules.iter().any(|r| r == rule.name.as_str()) {
+                continue;
+            }
+            let Some(file) = edge.dependencies.first() else {
+                continue;
+            };
+            commands.push(CompileCommand {
+                directory: directory.clone(),
+                command: edge.variable("command")?,
+                file: file.clone(),
+                output: edge.outputs.first().cloned().unwrap_or_default(),
+            });
+        }
+        Ok(commands)
+    }
+
+    /// Create the content of `compile_commands.json` for the build edges that use the given rules
+    ///
+    /// See [`compile_commands`](Self::compile_commands)
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -DNAME=\"$name\" -c $in -o $out");
+    /// cc.build(["foo.o"]).with(["foo.c"]).variable("name", "foo");
+    /// cc.build(["bar.o"]).with(["bar.c"]).variable("name", "bar");
+    ///
+    /// assert_eq!(ninja.compile_commands_json("/src", ["cc"]).unwrap(), r###"[
+    ///   {
+    ///     "directory": "/src",
+    ///     "command": "gcc -DNAME=\"foo\" -c foo.c -o foo.o",
+    ///     "file": "foo.c",
+    ///     "output": "foo.o"
+    ///   },
+    ///   {
+    ///     "directory": "/src",
+    ///     "command": "gcc -DNAME=\"bar\" -c bar.c -o bar.o",
+    ///     "file": "bar.c",
+    ///     "output": "bar.o"
+    ///   }
+    /// ]
+    /// "###);
+    /// ```
+    pub fn compile_commands_json(
+        &self,
+        directory: impl ToArg,
+        rules: impl IntoIterator<Item = impl ToArg>,
+    ) -> Result<String, EvalError> {
+        let commands = self.compile_commands(directory, rules)?;
+        let mut output = String::from("[");
+        for (i, command) in commands.iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            // writing to a string never fails
+            let _ = write!(output, "\n  {}", command);
+        }
+        output.push_str("\n]\n");
+        Ok(output)
+    }
+}
+
+/// Write a string as a JSON string literal
+fn write_json_str(f: &mut impl Write, s: &str) -> FmtResult {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, Variables};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_empty() {
+        let ninja = Ninja::new();
+        ninja.rule("cc", "gcc -c $in -o $out").build(["foo.o"]);
+        assert_eq!(ninja.compile_commands_json("/", ["cc"]).unwrap(), "[\n]\n");
+    }
+
+    #[test]
+    fn test_escape() {
+        let command = CompileCommand {
+            directory: "C:\\src".to_string(),
+            command: "a\"b\tc\nd\u{1}".to_string(),
+            file: "\u{4f60}".to_string(),
+            output: String::new(),
+        };
+        assert_eq!(
+            command.to_string(),
+            "{\n    \"directory\": \"C:\\\\src\",\n    \"command\": \"a\\\"b\\tc\\nd\\u0001\",\n    \"file\": \"\u{4f60}\",\n    \"output\": \"\"\n  }"
+        );
+    }
+
+    #[test]
+    fn test_rule_filter() {
+        let ninja = Ninja::new();
+        let cc = ninja.rule("cc", "gcc -c $in -o $out");
+        let cxx = ninja.rule("cxx", "g++ -c $in -o $out");
+        cc.build(["a.o"]).with(["a.c", "b.c"]);
+        cxx.build(["c.o"]).with(["c.cpp"]).variable("x", "y");
+        ninja.phony(["all"]).with(["a.o", "c.o"]);
+        let commands = ninja.compile_commands("/", ["cxx", "cc"]).unwrap();
+        let files: Vec<_> = commands.iter().map(|c| c.file.as_str()).collect();
+        assert_eq!(files, ["a.c", "c.cpp"]);
+        assert_eq!(commands[0].command, "gcc -c a.c b.c -o a.o");
+    }
+}
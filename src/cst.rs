@@ -0,0 +1,208 @@
+//! Lossless concrete syntax tree
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result};
+
+use crate::parse::{ParseError, Parser};
+use crate::stmt::Stmt;
+use crate::util::RefCounted;
+
+/// A ninja file parsed without losing comments or formatting
+///
+/// Each statement is kept as a [`CstNode`] along with its original text,
+/// including spacing, `$\n` continuations and comments inside the block.
+/// Comments and blank lines between statements are kept as the trivia of the
+/// next node. Converting the tree back to string gives the exact input,
+/// except for the nodes that are modified, which are formatted the same way as [`Ninja`](crate::Ninja).
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let input = r###"# compile
+/// build foo.o: cc foo.c $
+///     foo.h
+///
+/// ## link
+/// build app:   ld foo.o
+/// "###;
+/// let mut cst = Cst::parse(input).unwrap();
+/// assert_eq!(cst.to_string(), input);
+///
+/// // add an input to the link edge, the rest of the file is untouched
+/// if let Stmt::Build(build) = cst.nodes[1].stmt_mut() {
+///     build.dependencies.add("bar.o".to_string());
+/// }
+/// assert_eq!(cst.to_string(), r###"# compile
+/// build foo.o: cc foo.c $
+///     foo.h
+///
+/// ## link
+/// build app: ld foo.o bar.o
+/// "###);
+/// ```
+#[derive(Debug, Default)]
+pub struct Cst {
+    /// The statements in the file
+    pub nodes: Vec<CstNode>,
+    /// Comments and blank lines after the last statement
+    pub trailing_trivia: String,
+}
+
+/// A statement in a [`Cst`], with its original text
+#[derive(Debug)]
+pub struct CstNode {
+    /// Comments and blank lines before the statement
+    pub trivia: String,
+    /// The original text of the statement, or `None` if the statement is new or modified
+    text: Option<String>,
+    /// The statement
+    stmt: Stmt,
+}
+
+impl Cst {
+    /// Parse the input into a lossless syntax tree
+    ///
+    /// Top-level comments are kept as trivia and are not parsed as [`Stmt::Comment`].
+    pub fn parse(input: &str) -> core::result::Result<Self, ParseError> {
+        let mut parser = Parser::new(input, RefCounted::new("phony".to_string()));
+        let mut nodes = Vec::new();
+        let mut trivia_start = 0;
+        while let Some((start, stmt)) = parser.next_stmt()? {
+            if matches!(stmt, Stmt::Comment(_)) {
+                continue;
+            }
+            let end = parser.pos();
+            nodes.push(CstNode {
+                trivia: input[trivia_start..start].to_string(),
+                text: Some(input[start..end].to_string()),
+                stmt,
+            });
+            trivia_start = end;
+        }
+        Ok(Self {
+            nodes,
+            trailing_trivia: input[trivia_start..].to_string(),
+        })
+    }
+}
+
+impl Display for Cst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for node in &self.nodes {
+            node.fmt(f)?;
+        }
+        write!(f, "{}", self.trailing_trivia)
+    }
+}
+
+impl CstNode {
+    /// Create a new node from a statement, without any trivia
+    pub fn new(stmt: Stmt) -> Self {
+        Self {
+            trivia: String::new(),
+            text: None,
+            stmt,
+        }
+    }
+
+    /// Get the statement
+    #[inline]
+    pub fn stmt(&self) -> &Stmt {
+        &self.stmt
+    }
+
+    /// Get the statement for modification.
+    ///
+    /// The original text is discarded, and the statement will be formatted when
+    /// converting the tree to string.
+    pub fn stmt_mut(&mut self) -> &mut Stmt {
+        self.text = None;
+        &mut self.stmt
+    }
+
+    /// Replace the statement
+    pub fn set_stmt(&mut self, stmt: Stmt) {
+        *self.stmt_mut() = stmt;
+    }
+
+    /// If the statement is new or modified
+    #[inline]
+    pub fn is_modified(&self) -> bool {
+        self.text.is_none()
+    }
+
+    /// Get the text of the statement, which is the original text if not modified
+    pub fn text(&self) -> Cow<'_, str> {
+        match &self.text {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(self.stmt.to_string()),
+        }
+    }
+}
+
+impl Display for CstNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.trivia)?;
+        match &self.text {
+            Some(text) => write!(f, "{}", text),
+            None => self.stmt.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    use crate::{Build, BuildVariables, Rule};
+
+    const INPUT: &str = "\r\n# header\n\nx = 1 $\n  2\nrule cc\n  # inside\n    command   =  gcc $in\n\n\nbuild   a.o :cc a.c\n# trailing\n";
+
+    #[test]
+    fn test_lossless() {
+        let cst = Cst::parse(INPUT).unwrap();
+        assert_eq!(cst.to_string(), INPUT);
+        let trivia: Vec<_> = cst.nodes.iter().map(|n| n.trivia.as_str()).collect();
+        assert_eq!(trivia, vec!["\r\n# header\n\n", "", "\n\n"]);
+        let text: Vec<_> = cst.nodes.iter().map(|n| n.text()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "x = 1 $\n  2\n",
+                "rule cc\n  # inside\n    command   =  gcc $in\n",
+                "build   a.o :cc a.c\n"
+            ]
+        );
+        assert_eq!(cst.trailing_trivia, "# trailing\n");
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        let input = "default a\n\nx = y";
+        let cst = Cst::parse(input).unwrap();
+        assert_eq!(cst.to_string(), input);
+        assert_eq!(cst.nodes[1].text(), "x = y");
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut cst = Cst::parse(INPUT).unwrap();
+        assert!(!cst.nodes[2].is_modified());
+        let rule = Rule::new("ld", "gcc $in -o $out");
+        cst.nodes[2].set_stmt(Stmt::Build(Box::new(
+            Build::new(&rule, ["a"]).with(["a.o"]),
+        )));
+        assert!(cst.nodes[2].is_modified());
+        cst.nodes
+            .push(CstNode::new(Stmt::Default(vec!["a".to_string()])));
+        assert_eq!(
+            cst.to_string(),
+            "\r\n# header\n\nx = 1 $\n  2\nrule cc\n  # inside\n    command   =  gcc $in\n\n\nbuild a: ld a.o\ndefault a\n# trailing\n"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! The error type for fallible conversions
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Error from the `try_` methods, such as [`Ninja::try_rule`](crate::Ninja::try_rule)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Bytes that are not valid UTF-8
+    InvalidUtf8(Vec<u8>),
+    /// A path or OS string that is not valid UTF-8
+    #[cfg(feature = "std")]
+    InvalidUtf8Path(std::path::PathBuf),
+    /// Text in a command that can't be quoted for the shell, see
+    /// [`ShellCommand::try_from_command`](crate::ShellCommand::try_from_command)
+    Unquotable(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::InvalidUtf8(bytes) => write!(
+                f,
+                "argument is not valid UTF-8: {}",
+                String::from_utf8_lossy(bytes)
+            ),
+            #[cfg(feature = "std")]
+            Self::InvalidUtf8Path(path) => {
+                write!(f, "path is not valid UTF-8: {}", path.display())
+            }
+            Self::Unquotable(text) => {
+                write!(f, "text can't be quoted for the shell: {:?}", text)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,659 @@
+//! Evaluation of variables with ninja's scoping rules
+
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::ops::Deref;
+
+use crate::bytes::stored_byte;
+use crate::shell::{posix_quote, win32_quote};
+use crate::stmt::Stmt;
+use crate::util::RefCounted;
+use crate::{Build, Rule};
+
+/// The result of evaluating the variables of a ninja file
+///
+/// Variables are evaluated with the same rules as [ninja](https://ninja-build.org/manual.html#ref_scope):
+/// - Top-level variables are expanded immediately, in the order of the statements.
+/// - Paths and variables of a build edge are also expanded immediately. Variables of the build
+///   edge shadow top-level variables, and can be used in the paths of the same edge.
+/// - Rule variables are expanded lazily in the scope of each build edge, after all statements
+///   are evaluated. They can use `$in`, `$out` and `$in_newline`.
+///
+/// Undefined variables are expanded to empty strings.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// ninja.variable("cflags", "-Wall");
+/// ninja.variable("cflags", "$cflags -Werror");
+/// let cc = ninja.rule("cc", "gcc $cflags -c $in -o $out");
+/// let foo = cc.build(["foo.o"]).with(["foo.c"]);
+/// let bar = cc.build(["bar.o"]).with(["bar.c"])
+///     .variable("cflags", "$cflags -DBAR");
+///
+/// let eval = ninja.evaluate();
+/// assert_eq!(eval.variable("cflags"), Some("-Wall -Werror"));
+/// let foo = eval.edge(&foo).unwrap();
+/// assert_eq!(foo.variable("cflags").unwrap(), "-Wall -Werror");
+/// assert_eq!(foo.variable("command").unwrap(), "gcc -Wall -Werror -c foo.c -o foo.o");
+/// let bar = eval.edge(&bar).unwrap();
+/// assert_eq!(bar.variable("command").unwrap(), "gcc -Wall -Werror -DBAR -c bar.c -o bar.o");
+/// ```
+#[derive(Debug)]
+pub struct Evaluation {
+    /// The scopes, where the first one is the root scope
+    scopes: Vec<Scope>,
+    /// The build edges, in the order of the statements
+    pub(crate) edges: Vec<Edge>,
+    /// Index of the edges by the address of the [`Build`]
+    edge_index: BTreeMap<usize, usize>,
+    /// The evaluated targets of `default` statements
+    pub defaults: Vec<String>,
+}
+
+/// A scope of variables and rules
+#[derive(Debug, Default)]
+struct Scope {
+    /// The parent scope
+    parent: Option<usize>,
+    /// The evaluated variables
+    variables: BTreeMap<String, String>,
+    /// The rules declared in this scope
+    rules: BTreeMap<String, RefCounted<Stmt>>,
+}
+
+/// A build edge with its paths and variables evaluated
+#[derive(Debug)]
+pub struct Edge {
+    /// The build statement
+    pub stmt: RefCounted<Stmt>,
+    /// Index of the statement
+    pub index: usize,
+    /// Index of the scope the edge is in
+    pub scope: usize,
+    /// The rule statement, or `None` if it is `phony` or is not declared
+    pub rule: Option<RefCounted<Stmt>>,
+    /// The evaluated explicit outputs
+    pub outputs: Vec<String>,
+    /// The evaluated implicit outputs
+    pub implicit_outputs: Vec<String>,
+    /// The evaluated explicit dependencies
+    pub dependencies: Vec<String>,
+    /// The evaluated implicit dependencies
+    pub implicit_dependencies: Vec<String>,
+    /// The evaluated order-only dependencies
+    pub order_only_dependencies: Vec<String>,
+    /// The evaluated validations
+    pub validations: Vec<String>,
+    /// The evaluated variables of the build edge
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Edge {
+    /// Get the build statement of the edge, or `None` if the statement is not a build
+    pub fn build(&self) -> Option<&Build> {
+        match self.stmt.as_ref() {
+            Stmt::Build(build) => Some(build),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the rule the edge uses, or an empty string if the statement
+    /// is not a build
+    pub(crate) fn rule_name(&self) -> &str {
+        self.build().map_or("", |build| build.rule.as_str())
+    }
+
+    /// Get the rule of the edge, or `None` if it is `phony` or is not declared
+    pub fn rule(&self) -> Option<&Rule> {
+        match self.rule.as_deref() {
+            Some(Stmt::Rule(rule)) => Some(rule),
+            _ => None,
+        }
+    }
+}
+
+/// Error when evaluating rule variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// Rule variables reference each other in a cycle. The first and last names are the same.
+    Cycle(Vec<String>),
+    /// The build edge was not found in the evaluated statements
+    EdgeNotFound,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Cycle(names) => write!(f, "cycle in rule variables: {}", names.join(" -> ")),
+            Self::EdgeNotFound => write!(f, "build edge not found"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EvalError {}
+
+/// The fully expanded variables that ninja uses to run a build edge
+///
+/// Variables that are not set are empty.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// ninja.variable("cflags", "-Wall");
+/// let cc = ninja.rule("cc", "gcc -MD -MF $out.d $cflags -c $in -o $out")
+///     .description("CC $out")
+///     .depfile("$out.d");
+/// let ar = ninja.rule("ar", "ar rcs $out @$out.rsp")
+///     .rspfile("$out.rsp", "$in_newline");
+///
+/// let foo = cc.build(["foo.o"]).with(["foo.c"]).variable("cflags", "$cflags -O2");
+/// let lib = ar.build(["libfoo.a"]).with(["foo.o", "bar.o"]);
+///
+/// let expanded = foo.expand().unwrap();
+/// assert_eq!(expanded.command, "gcc -MD -MF foo.o.d -Wall -O2 -c foo.c -o foo.o");
+/// assert_eq!(expanded.description, "CC foo.o");
+/// assert_eq!(expanded.depfile, "foo.o.d");
+/// assert_eq!(expanded.rspfile, "");
+///
+/// let expanded = lib.expand().unwrap();
+/// assert_eq!(expanded.command, "ar rcs libfoo.a @libfoo.a.rsp");
+/// assert_eq!(expanded.rspfile, "libfoo.a.rsp");
+/// assert_eq!(expanded.rspfile_content, "foo.o\nbar.o");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Expanded {
+    /// The command to run
+    pub command: String,
+    /// The description printed when running the command
+    pub description: String,
+    /// The depfile to read after running the command
+    pub depfile: String,
+    /// The response file to create before running the command
+    pub rspfile: String,
+    /// The content of the response file
+    pub rspfile_content: String,
+}
+
+impl Evaluation {
+    /// Get the value of a variable in the root scope after all statements are evaluated
+    #[inline]
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.scope_variable(0, name)
+    }
+
+    /// Get the value of a variable in a scope after all statements are evaluated.
+    ///
+    /// Variables from parent scopes are visible.
+    pub fn scope_variable(&self, scope: usize, name: &str) -> Option<&str> {
+        let mut scope = Some(scope);
+        while let Some(s) = scope.and_then(|s| self.scopes.get(s)) {
+            if let Some(value) = s.variables.get(name) {
+                return Some(value);
+            }
+            scope = s.parent;
+        }
+        None
+    }
+
+    /// Get the evaluated build edge
+    pub fn edge(&self, build: impl AsRef<Build>) -> Option<EdgeEnv<'_>> {
+        let address = build.as_ref() as *const Build as usize;
+        let edge = &self.edges[*self.edge_index.get(&address)?];
+        Some(EdgeEnv { eval: self, edge })
+    }
+
+    /// Iterate over the build edges in the order of the statements
+    pub fn edges(&self) -> impl Iterator<Item = EdgeEnv<'_>> {
+        self.edges.iter().map(|edge| EdgeEnv { eval: self, edge })
+    }
+
+    /// Find a rule visible in the scope
+    fn find_rule(&self, scope: usize, name: &str) -> Option<&RefCounted<Stmt>> {
+        let mut scope = Some(scope);
+        while let Some(s) = scope.and_then(|s| self.scopes.get(s)) {
+            if let Some(rule) = s.rules.get(name) {
+                return Some(rule);
+            }
+            scope = s.parent;
+        }
+        None
+    }
+}
+
+/// A build edge in an [`Evaluation`], which can be used to evaluate rule variables
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeEnv<'a> {
+    pub(crate) eval: &'a Evaluation,
+    /// The evaluated edge
+    pub edge: &'a Edge,
+}
+
+impl Deref for EdgeEnv<'_> {
+    type Target = Edge;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.edge
+    }
+}
+
+impl EdgeEnv<'_> {
+    /// Get the value of a variable as seen by the rule of this build edge.
+    ///
+    /// The variable is looked up in this order:
+    /// 1. `$in`, `$in_newline` and `$out`
+    /// 2. The variables of the build edge
+    /// 3. The variables of the rule, evaluated in this order
+    /// 4. The scope of the build edge (i.e. top-level variables)
+    pub fn variable(&self, name: &str) -> Result<String, EvalError> {
+        self.lookup(name, &mut Vec::new())
+    }
+
+    /// Expand the variables that are used to run the build edge
+    ///
+    /// See [`Expanded`] for an example.
+    pub fn expand(&self) -> Result<Expanded, EvalError> {
+        Ok(Expanded {
+            command: self.variable("command")?,
+            description: self.variable("description")?,
+            depfile: self.variable("depfile")?,
+            rspfile: self.variable("rspfile")?,
+            rspfile_content: self.variable("rspfile_content")?,
+        })
+    }
+
+    fn lookup(&self, name: &str, stack: &mut Vec<String>) -> Result<String, EvalError> {
+        match name {
+            "in" => return Ok(path_list(&self.dependencies, " ")),
+            "in_newline" => return Ok(path_list(&self.dependencies, "\n")),
+            "out" => return Ok(path_list(&self.outputs, " ")),
+            _ => {}
+        }
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(rule) = self.rule() {
+            let variables = rule.variables.inner();
+            if let Some(binding) = variables.iter().rev().find(|v| v.name == name) {
+                if stack.iter().any(|n| n == name) {
+                    stack.push(name.to_string());
+                    let start = stack.iter().position(|n| n == name).unwrap_or_default();
+                    return Err(EvalError::Cycle(stack.split_off(start)));
+                }
+                stack.push(name.to_string());
+                let value = evaluate(&binding.value.escaped(), |var| self.lookup(var, stack))?;
+                stack.pop();
+                return Ok(value);
+            }
+        }
+        Ok(self
+            .eval
+            .scope_variable(self.scope, name)
+            .unwrap_or_default()
+            .to_string())
+    }
+}
+
+/// Incremental evaluation of statements
+#[derive(Debug)]
+pub(crate) struct Evaluator {
+    eval: Evaluation,
+}
+
+impl Evaluator {
+    /// Create an evaluator with the parent of each scope
+    pub fn new(scopes: impl IntoIterator<Item = Option<usize>>) -> Self {
+        let mut scopes: Vec<_> = scopes
+            .into_iter()
+            .map(|parent| Scope {
+                parent,
+                ..Default::default()
+            })
+            .collect();
+        if scopes.is_empty() {
+            scopes.push(Scope::default());
+        }
+        Self {
+            eval: Evaluation {
+                scopes,
+                edges: Vec::new(),
+                edge_index: BTreeMap::new(),
+                defaults: Vec::new(),
+            },
+        }
+    }
+
+    /// Add a scope and return its index
+    #[cfg(feature = "std")]
+    pub fn add_scope(&mut self, parent: usize) -> usize {
+        self.eval.scopes.push(Scope {
+            parent: Some(parent),
+            ..Default::default()
+        });
+        self.eval.scopes.len() - 1
+    }
+
+    /// Evaluate a path or value in a scope with the current variables
+    pub fn evaluate_in(&self, scope: usize, text: &str) -> String {
+        let lookup = |name: &str| -> Result<String, EvalError> {
+            Ok(self
+                .eval
+                .scope_variable(scope, name)
+                .unwrap_or_default()
+                .to_string())
+        };
+        evaluate(text, lookup).unwrap_or_default()
+    }
+
+    /// Evaluate the next statement
+    pub fn push(&mut self, stmt: &RefCounted<Stmt>, index: usize, scope: usize) {
+        match stmt.as_ref() {
+            Stmt::Variable(variable) => {
+                let value = self.evaluate_in(scope, &variable.value.escaped());
+                self.eval.scopes[scope]
+                    .variables
+                    .insert(variable.name.clone(), value);
+            }
+            Stmt::Rule(rule) => {
+                self.eval.scopes[scope]
+                    .rules
+                    .insert(rule.name.to_string(), RefCounted::clone(stmt));
+            }
+            Stmt::Build(build) => {
+                let edge = self.evaluate_build(stmt, build, index, scope);
+                self.eval.edge_index.insert(
+                    build.as_ref() as *const Build as usize,
+                    self.eval.edges.len(),
+                );
+                self.eval.edges.push(edge);
+            }
+            Stmt::Default(outputs) => {
+                for output in outputs {
+                    let output = self.evaluate_in(scope, output);
+                    self.eval.defaults.push(output);
+                }
+            }
+            Stmt::Comment(_) | Stmt::Subninja(_) | Stmt::Include(_) | Stmt::Pool(_) => {}
+        }
+    }
+
+    fn evaluate_build(
+        &self,
+        stmt: &RefCounted<Stmt>,
+        build: &Build,
+        index: usize,
+        scope: usize,
+    ) -> Edge {
+        // build variables are evaluated in the outer scope
+        let variables: BTreeMap<_, _> = build
+            .variables
+            .inner()
+            .iter()
+            .map(|v| (v.name.clone(), self.evaluate_in(scope, &v.value.escaped())))
+            .collect();
+        // paths can also see the build variables
+        let lookup = |name: &str| -> Result<String, EvalError> {
+            let value = variables
+                .get(name)
+                .map(String::as_str)
+                .or_else(|| self.eval.scope_variable(scope, name));
+            Ok(value.unwrap_or_default().to_string())
+        };
+        let paths = |list: &crate::util::SharedVec<String>| -> Vec<String> {
+            list.inner()
+                .iter()
+                .map(|p| evaluate(p, lookup).unwrap_or_default())
+                .collect()
+        };
+        Edge {
+            stmt: RefCounted::clone(stmt),
+            index,
+            scope,
+            rule: self.eval.find_rule(scope, &build.rule).cloned(),
+            outputs: paths(&build.outputs),
+            implicit_outputs: paths(&build.implicit_outputs),
+            dependencies: paths(&build.dependencies),
+            implicit_dependencies: paths(&build.implicit_dependencies),
+            order_only_dependencies: paths(&build.order_only_dependencies),
+            validations: paths(&build.validations),
+            variables,
+        }
+    }
+
+    /// Finish evaluating
+    #[inline]
+    pub fn finish(self) -> Evaluation {
+        self.eval
+    }
+}
+
+/// Evaluate a list of statements in one scope
+pub(crate) fn evaluate_list(stmts: &[RefCounted<Stmt>]) -> Evaluation {
+    let mut evaluator = Evaluator::new([None]);
+    for (i, stmt) in stmts.iter().enumerate() {
+        evaluator.push(stmt, i, 0);
+    }
+    evaluator.finish()
+}
+
+/// Evaluate an escaped ninja string, using the lookup function to get the value of variables
+pub(crate) fn evaluate(
+    text: &str,
+    mut lookup: impl FnMut(&str) -> Result<String, EvalError>,
+) -> Result<String, EvalError> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        output.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(c) = after.chars().next().filter(|c| stored_byte(*c).is_some()) {
+            // bytes that are not valid UTF-8, see `Bytes`
+            output.push(char::REPLACEMENT_CHARACTER);
+            rest = &after[c.len_utf8()..];
+            continue;
+        }
+        let bytes = after.as_bytes();
+        rest = match bytes.first() {
+            Some(b'$' | b' ' | b':') => {
+                output.push_str(&after[..1]);
+                &after[1..]
+            }
+            Some(b'\n') => after[1..].trim_start_matches(' '),
+            Some(b'\r') if bytes.get(1) == Some(&b'\n') => after[2..].trim_start_matches(' '),
+            Some(b'{') => match after.find('}') {
+                Some(end) => {
+                    output.push_str(&lookup(&after[1..end])?);
+                    &after[end + 1..]
+                }
+                None => {
+                    output.push('$');
+                    after
+                }
+            },
+            _ => {
+                let len = bytes
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-'))
+                    .count();
+                if len == 0 {
+                    output.push('$');
+                } else {
+                    output.push_str(&lookup(&after[..len])?);
+                }
+                &after[len..]
+            }
+        };
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Join the paths for `$in` and `$out`, quoting them for the shell the same way as ninja
+fn path_list(paths: &[String], separator: &str) -> String {
+    let mut output = String::new();
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            output.push_str(separator);
+        }
+        if cfg!(windows) {
+            output.push_str(&win32_escape(path));
+        } else {
+            output.push_str(&shell_escape(path));
+        }
+    }
+    output
+}
+
+/// Quote a path for POSIX shell if it contains characters that are not known to be safe
+fn shell_escape(path: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.' | '/');
+    if path.chars().all(is_safe) {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(posix_quote(path))
+}
+
+/// Quote a path for Windows command lines if it contains spaces or quotes
+fn win32_escape(path: &str) -> Cow<'_, str> {
+    if !path.contains([' ', '"']) {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(win32_quote(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, Ninja, RuleVariables, Variables};
+
+    fn eval(text: &str, vars: &[(&str, &str)]) -> String {
+        evaluate(text, |name| {
+            Ok(vars
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+                .unwrap_or_default())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_escapes() {
+        assert_eq!(eval("a$$b$ c$:d", &[]), "a$b c:d");
+        assert_eq!(eval("a $\n    b", &[]), "a b");
+        assert_eq!(eval("a$\r\n  b", &[]), "ab");
+        assert_eq!(
+            eval("$x.$y-z ${x}y $undefined.", &[("x", "1"), ("y-z", "2")]),
+            "1.2 1y ."
+        );
+        assert_eq!(eval("${a.b}", &[("a.b", "1")]), "1");
+    }
+
+    #[test]
+    fn test_rule_variables_are_lazy() {
+        let ninja = Ninja::parse(
+            "cflags = -O0\nrule cc\n  command = gcc $cflags\nbuild a: cc a.c\n  x = $cflags\ncflags = -O2\n",
+        )
+        .unwrap();
+        let eval = ninja.evaluate();
+        let edge = eval.edges().next().unwrap();
+        assert_eq!(edge.variable("x").unwrap(), "-O0");
+        assert_eq!(edge.variable("command").unwrap(), "gcc -O2");
+    }
+
+    #[test]
+    fn test_paths() {
+        let ninja = Ninja::new();
+        ninja.variable("dir", "out");
+        let rule = ninja.rule("cp", "cp $in $out");
+        let build = rule
+            .build(["$dir/$name", "$dir/b$ c"])
+            .with(["$name.in", "it's"])
+            .variable("name", "a");
+        let eval = ninja.evaluate();
+        let edge = eval.edge(&build).unwrap();
+        assert_eq!(edge.outputs, ["out/a", "out/b c"]);
+        assert_eq!(edge.dependencies, ["a.in", "it's"]);
+        if !cfg!(windows) {
+            assert_eq!(
+                edge.variable("command").unwrap(),
+                "cp a.in 'it'\\''s' out/a 'out/b c'"
+            );
+            assert_eq!(edge.variable("in_newline").unwrap(), "a.in\n'it'\\''s'");
+        }
+    }
+
+    #[test]
+    fn test_rule_variables() {
+        let ninja = Ninja::new();
+        ninja.variable("desc", "top");
+        let rule = ninja
+            .rule("r", "$a")
+            .variable("a", "$b")
+            .variable("b", "$desc")
+            .description("$b!");
+        let build = rule.build(["x"]);
+        let build2 = rule.build(["y"]).variable("desc", "build");
+        let build3 = rule.build(["z"]).variable("b", "$a");
+        let eval = ninja.evaluate();
+        let edge = eval.edge(&build).unwrap();
+        assert_eq!(edge.variable("command").unwrap(), "top");
+        assert_eq!(edge.variable("description").unwrap(), "top!");
+        let edge = eval.edge(&build2).unwrap();
+        assert_eq!(edge.variable("command").unwrap(), "build");
+        // build variables are not evaluated lazily
+        let edge = eval.edge(&build3).unwrap();
+        assert_eq!(edge.variable("command").unwrap(), "");
+    }
+
+    #[test]
+    fn test_cycle() {
+        let ninja = Ninja::new();
+        let rule = ninja
+            .rule("r", "$a")
+            .variable("a", "$b")
+            .variable("b", "$a");
+        let build = rule.build(["x"]);
+        let eval = ninja.evaluate();
+        let edge = eval.edge(&build).unwrap();
+        let err = edge.variable("command").unwrap_err();
+        assert_eq!(
+            err,
+            EvalError::Cycle(["a", "b", "a"].map(String::from).to_vec())
+        );
+        assert_eq!(err.to_string(), "cycle in rule variables: a -> b -> a");
+    }
+
+    #[test]
+    fn test_undeclared_rule() {
+        let ninja = Ninja::new();
+        let rule = crate::Rule::new("r", "echo");
+        ninja.variable("command", "top");
+        let build = crate::BuildRef(ninja.add_stmt(Stmt::Build(alloc::boxed::Box::new(
+            crate::Build::new(&rule, ["x"]),
+        ))));
+        let eval = ninja.evaluate();
+        let edge = eval.edge(&build).unwrap();
+        assert!(edge.rule().is_none());
+        assert_eq!(edge.variable("command").unwrap(), "top");
+    }
+
+    #[test]
+    fn test_shell_escape() {
+        assert_eq!(shell_escape("a/b_c+d-e.f"), "a/b_c+d-e.f");
+        assert_eq!(shell_escape("a b"), "'a b'");
+        assert_eq!(shell_escape("it's"), "'it'\\''s'");
+        assert_eq!(win32_escape("a\\b"), "a\\b");
+        assert_eq!(win32_escape("a b\\"), "\"a b\\\\\"");
+        assert_eq!(win32_escape("a\\\"b"), "\"a\\\\\\\"b\"");
+    }
+}
@@ -0,0 +1,473 @@
+//! Build graph queries
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::{EdgeEnv, Evaluation, Ninja};
+
+/// How a path is produced by a build edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OutputKind {
+    /// An explicit output (`build <out>: ...`)
+    Explicit,
+    /// An implicit output (`build ... | <out>: ...`)
+    Implicit,
+}
+
+/// How a path is used by a build edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DepKind {
+    /// An explicit dependency (`build ...: rule <in>`)
+    Explicit,
+    /// An implicit dependency (`build ...: rule ... | <in>`)
+    Implicit,
+    /// An order-only dependency (`build ...: rule ... || <in>`)
+    OrderOnly,
+    /// A validation (`build ...: rule ... |@ <in>`)
+    Validation,
+}
+
+/// A view of the build edges as a graph of files
+///
+/// The paths are evaluated and unescaped, the same as [`Edge`](crate::Edge).
+/// If multiple edges produce the same output, the first one is used.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+/// let ld = ninja.rule("ld", "gcc $in -o $out");
+/// let script = ninja.rule("gen", "./gen.sh $out");
+/// script.build(["gen.h"]);
+/// cc.build(["foo.o"]).with(["foo.c"]).with_implicit(["gen.h"]);
+/// cc.build(["bar.o"]).with(["bar.c"]).with_order_only(["gen.h"]);
+/// ld.build(["app"]).with(["foo.o", "bar.o"]).output_implicit(["app.map"]);
+///
+/// let graph = ninja.graph();
+/// let (edge, kind) = graph.producer("app.map").unwrap();
+/// assert_eq!(edge.outputs, ["app"]);
+/// assert_eq!(kind, OutputKind::Implicit);
+///
+/// assert_eq!(graph.inputs("foo.o"), [("foo.c", DepKind::Explicit), ("gen.h", DepKind::Implicit)]);
+/// assert_eq!(graph.transitive_inputs("app"), ["foo.o", "foo.c", "gen.h", "bar.o", "bar.c"]);
+///
+/// let users = graph.dependents("gen.h");
+/// assert_eq!(users[0].0.outputs, ["foo.o"]);
+/// assert_eq!(users[0].1, DepKind::Implicit);
+/// assert_eq!(users[1].0.outputs, ["bar.o"]);
+/// assert_eq!(users[1].1, DepKind::OrderOnly);
+///
+/// assert_eq!(graph.edges_with_rule("cc").count(), 2);
+/// assert_eq!(graph.sources(), ["bar.c", "foo.c"]);
+/// ```
+#[derive(Debug)]
+pub struct Graph {
+    /// The evaluated statements
+    pub evaluation: Evaluation,
+    /// Index of the edge that produces each output
+    producers: BTreeMap<String, (usize, OutputKind)>,
+    /// Index of the edges that use each input
+    consumers: BTreeMap<String, Vec<(usize, DepKind)>>,
+}
+
+impl Graph {
+    /// Create the graph from the evaluated statements
+    pub fn new(evaluation: Evaluation) -> Self {
+        let mut producers = BTreeMap::new();
+        let mut consumers: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (i, edge) in evaluation.edges.iter().enumerate() {
+            for (outputs, kind) in [
+                (&edge.outputs, OutputKind::Explicit),
+                (&edge.implicit_outputs, OutputKind::Implicit),
+            ] {
+                for output in outputs {
+                    producers.entry(output.clone()).or_insert((i, kind));
+                }
+            }
+            for (input, kind) in inputs_of(edge) {
+                consumers.entry(input.into()).or_default().push((i, kind));
+            }
+        }
+        Self {
+            evaluation,
+            producers,
+            consumers,
+        }
+    }
+
+    /// Get the build edge that produces the path, and how it is produced
+    pub fn producer(&self, path: &str) -> Option<(EdgeEnv<'_>, OutputKind)> {
+        let (i, kind) = self.producers.get(path)?;
+        Some((self.edge_at(*i), *kind))
+    }
+
+    /// Get the direct inputs of the build edge that produces the target, and how they are used.
+    ///
+    /// Returns an empty list if no build edge produces the target
+    pub fn inputs(&self, target: &str) -> Vec<(&str, DepKind)> {
+        match self.producers.get(target) {
+            Some((i, _)) => inputs_of(&self.evaluation.edges[*i]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get all the inputs needed to build the target, directly or indirectly.
+    ///
+    /// Explicit, implicit and order-only dependencies are followed, but not validations.
+    /// The inputs are in depth-first order and each input is only listed once.
+    pub fn transitive_inputs(&self, target: &str) -> Vec<&str> {
+        let mut visited = BTreeSet::new();
+        let mut output = Vec::new();
+        let mut stack = Vec::from([(self.dependencies(target), 0)]);
+        while let Some((dependencies, next)) = stack.last_mut() {
+            let Some(&input) = dependencies.get(*next) else {
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            if visited.insert(input) {
+                output.push(input);
+                stack.push((self.dependencies(input), 0));
+            }
+        }
+        output
+    }
+
+    /// Find the dependency cycles, which ninja refuses to build (`dependency cycle: ...`).
+    ///
+    /// Explicit, implicit and order-only dependencies are followed, including through
+    /// phony edges, but not validations. Each cycle is reported once, starting and
+    /// ending with the same path.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// let script = ninja.rule("gen", "./gen.sh $in > $out");
+    /// cc.build(["a.o"]).with(["a.c"]).with_order_only(["headers"]);
+    /// ninja.phony(["headers"]).with(["gen.h"]);
+    /// script.build(["gen.h"]).with_implicit(["a.o"]);
+    ///
+    /// let cycles = ninja.graph().cycles();
+    /// assert_eq!(cycles.len(), 1);
+    /// assert_eq!(cycles[0].path, ["a.o", "headers", "gen.h", "a.o"]);
+    /// assert_eq!(cycles[0].to_string(), "dependency cycle: a.o -> headers -> gen.h -> a.o");
+    /// ```
+    pub fn cycles(&self) -> Vec<DependencyCycle> {
+        // false if the path is being visited, true if it is done
+        let mut visited = BTreeMap::new();
+        let mut cycles = Vec::new();
+        for start in self.producers.keys() {
+            if visited.contains_key(start.as_str()) {
+                continue;
+            }
+            visited.insert(start.as_str(), false);
+            let mut stack = Vec::from([(start.as_str(), self.dependencies(start), 0)]);
+            while let Some((path, dependencies, next)) = stack.last_mut() {
+                let Some(&dependency) = dependencies.get(*next) else {
+                    visited.insert(*path, true);
+                    stack.pop();
+                    continue;
+                };
+                *next += 1;
+                match visited.get(dependency) {
+                    Some(true) => {}
+                    Some(false) => {
+                        // safety: paths being visited are on the stack
+                        let pos = stack.iter().position(|(p, ..)| *p == dependency).unwrap();
+                        let mut path: Vec<String> = stack[pos..]
+                            .iter()
+                            .map(|(p, ..)| String::from(*p))
+                            .collect();
+                        path.push(dependency.into());
+                        cycles.push(DependencyCycle { path });
+                    }
+                    None => {
+                        visited.insert(dependency, false);
+                        stack.push((dependency, self.dependencies(dependency), 0));
+                    }
+                }
+            }
+        }
+        cycles
+    }
+
+    /// Get the inputs of the edge that produces the target, except validations
+    fn dependencies(&self, target: &str) -> Vec<&str> {
+        self.inputs(target)
+            .into_iter()
+            .filter(|(_, kind)| *kind != DepKind::Validation)
+            .map(|(input, _)| input)
+            .collect()
+    }
+
+    /// Get the build edges that use the path as an input, and how it is used
+    pub fn dependents(&self, path: &str) -> Vec<(EdgeEnv<'_>, DepKind)> {
+        match self.consumers.get(path) {
+            Some(consumers) => consumers
+                .iter()
+                .map(|(i, kind)| (self.edge_at(*i), *kind))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the build edges that use the rule
+    pub fn edges_with_rule<'a>(&'a self, rule: &'a str) -> impl Iterator<Item = EdgeEnv<'a>> {
+        self.evaluation
+            .edges()
+            .filter(move |edge| edge.rule_name() == rule)
+    }
+
+    /// Get the source files, which are the inputs that are not produced by any build edge,
+    /// in sorted order
+    pub fn sources(&self) -> Vec<&str> {
+        self.consumers
+            .keys()
+            .filter(|path| !self.producers.contains_key(*path))
+            .map(|path| path.as_str())
+            .collect()
+    }
+
+    /// Find the outputs that are produced by more than one build edge.
+    ///
+    /// Ninja refuses to load such a file (`multiple rules generate <output>`).
+    /// Every edge after the first that produces the same output is reported,
+    /// for both explicit and implicit outputs.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// let cp = ninja.rule("cp", "cp $in $out");
+    /// cc.build(["foo.o"]).with(["foo.c"]);
+    /// cp.build(["bar.o"]).with(["prebuilt/bar.o"]).output_implicit(["foo.o"]);
+    ///
+    /// let duplicates = ninja.graph().duplicate_outputs();
+    /// assert_eq!(duplicates, [DuplicateOutput {
+    ///     output: "foo.o".to_string(),
+    ///     first_rule: "cc".to_string(),
+    ///     first_stmt: 2,
+    ///     second_rule: "cp".to_string(),
+    ///     second_stmt: 3,
+    /// }]);
+    /// assert_eq!(
+    ///     duplicates[0].to_string(),
+    ///     "multiple rules generate foo.o (cc at statement 2, cp at statement 3)"
+    /// );
+    /// ```
+    pub fn duplicate_outputs(&self) -> Vec<DuplicateOutput> {
+        let mut duplicates = Vec::new();
+        for (i, edge) in self.evaluation.edges.iter().enumerate() {
+            let mut seen = BTreeSet::new();
+            for output in edge.outputs.iter().chain(&edge.implicit_outputs) {
+                let Some((first, _)) = self.producers.get(output) else {
+                    continue;
+                };
+                if *first == i || !seen.insert(output) {
+                    continue;
+                }
+                let first = &self.evaluation.edges[*first];
+                duplicates.push(DuplicateOutput {
+                    output: output.clone(),
+                    first_rule: first.rule_name().into(),
+                    first_stmt: first.index,
+                    second_rule: edge.rule_name().into(),
+                    second_stmt: edge.index,
+                });
+            }
+        }
+        duplicates
+    }
+
+    fn edge_at(&self, i: usize) -> EdgeEnv<'_> {
+        EdgeEnv {
+            eval: &self.evaluation,
+            edge: &self.evaluation.edges[i],
+        }
+    }
+}
+
+/// An output that is produced by more than one build edge
+///
+/// See [`Graph::duplicate_outputs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateOutput {
+    /// The evaluated output
+    pub output: String,
+    /// The rule of the first edge that produces the output
+    pub first_rule: String,
+    /// Index of the statement of the first edge
+    pub first_stmt: usize,
+    /// The rule of the other edge that produces the output
+    pub second_rule: String,
+    /// Index of the statement of the other edge
+    pub second_stmt: usize,
+}
+
+impl Display for DuplicateOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "multiple rules generate {} ({} at statement {}, {} at statement {})",
+            self.output, self.first_rule, self.first_stmt, self.second_rule, self.second_stmt
+        )
+    }
+}
+
+/// A cycle in the dependencies
+///
+/// See [`Graph::cycles`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyCycle {
+    /// The evaluated paths in the cycle, where the first and last are the same
+    pub path: Vec<String>,
+}
+
+impl Display for DependencyCycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "dependency cycle: {}", self.path.join(" -> "))
+    }
+}
+
+/// Iterate over the inputs of an edge and how they are used
+fn inputs_of(edge: &crate::Edge) -> impl Iterator<Item = (&str, DepKind)> {
+    [
+        (&edge.dependencies, DepKind::Explicit),
+        (&edge.implicit_dependencies, DepKind::Implicit),
+        (&edge.order_only_dependencies, DepKind::OrderOnly),
+        (&edge.validations, DepKind::Validation),
+    ]
+    .into_iter()
+    .flat_map(|(inputs, kind)| inputs.iter().map(move |input| (input.as_str(), kind)))
+}
+
+impl Ninja {
+    /// Create a graph view of the build edges for queries
+    ///
+    /// See [`Graph`] for examples.
+    #[inline]
+    pub fn graph(&self) -> Graph {
+        Graph::new(self.evaluate())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, Variables};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_deep_chain() {
+        let ninja = Ninja::new();
+        let cp = ninja.rule("cp", "cp $in $out");
+        let depth = 200_000;
+        for i in 1..=depth {
+            cp.build([i.to_string()]).with([(i - 1).to_string()]);
+        }
+        let graph = ninja.graph();
+        let inputs = graph.transitive_inputs(&depth.to_string());
+        assert_eq!(inputs.len(), depth);
+        assert_eq!(inputs[0], (depth - 1).to_string());
+        assert_eq!(inputs[depth - 1], "0");
+        assert!(graph.cycles().is_empty());
+    }
+
+    #[test]
+    fn test_evaluated_paths() {
+        let ninja = Ninja::new();
+        ninja.variable("dir", "out");
+        let cp = ninja.rule("cp", "cp $in $out");
+        cp.build(["$dir/a$ b"]).with(["a$ b"]);
+        cp.build(["$dir/c"])
+            .with(["$dir/a$ b"])
+            .validations(["check"]);
+        let graph = ninja.graph();
+        assert!(graph.producer("out/a b").is_some());
+        assert_eq!(graph.transitive_inputs("out/c"), ["out/a b", "a b"]);
+        assert_eq!(graph.sources(), ["a b", "check"]);
+    }
+
+    #[test]
+    fn test_missing() {
+        let ninja = Ninja::new();
+        let graph = ninja.graph();
+        assert!(graph.producer("a").is_none());
+        assert!(graph.inputs("a").is_empty());
+        assert!(graph.dependents("a").is_empty());
+        assert!(graph.transitive_inputs("a").is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_outputs() {
+        let ninja = Ninja::new();
+        ninja.variable("dir", "out");
+        let cp = ninja.rule("cp", "cp $in $out");
+        cp.build(["out/a", "out/a"]).with(["a"]);
+        ninja.phony(["$dir/a"]).output_implicit(["out/a"]);
+        cp.build(["b"]).output_implicit(["out/a"]);
+        let stmts: Vec<_> = ninja
+            .graph()
+            .duplicate_outputs()
+            .iter()
+            .map(|d| (d.second_rule.clone(), d.first_stmt, d.second_stmt))
+            .collect();
+        assert_eq!(
+            stmts,
+            [("phony".to_string(), 2, 3), ("cp".to_string(), 2, 4)]
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        let ninja = Ninja::new();
+        let cp = ninja.rule("cp", "cp $in $out");
+        ninja.phony(["self"]).with(["self"]);
+        cp.build(["x"]).with(["y"]).validations(["z"]);
+        cp.build(["z"]).with(["x"]);
+        cp.build(["y"]).with_implicit(["w"]);
+        cp.build(["w"]).with_order_only(["x"]);
+        let cycles: Vec<_> = ninja
+            .graph()
+            .cycles()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            cycles,
+            [
+                "dependency cycle: self -> self",
+                "dependency cycle: w -> x -> y -> w",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_cycles() {
+        let ninja = Ninja::new();
+        let cp = ninja.rule("cp", "cp $in $out");
+        cp.build(["a"]).with(["b", "c"]);
+        cp.build(["b"]).with(["c"]);
+        cp.build(["c"]).with(["d"]);
+        assert!(ninja.graph().cycles().is_empty());
+    }
+
+    #[test]
+    fn test_cycle_terminates() {
+        let ninja = Ninja::new();
+        ninja.phony(["a"]).with(["b"]);
+        ninja.phony(["b"]).with(["a"]).variable("x", "y");
+        let graph = ninja.graph();
+        assert_eq!(graph.transitive_inputs("a"), ["b", "a"]);
+        assert!(graph.sources().is_empty());
+    }
+}
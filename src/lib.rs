@@ -75,8 +75,15 @@
 //! ```
 //!
 //! ## Encoding
-//! Because `.to_string()` is used to get the output, All inputs/outputs are expected to be UTF-8
-//! encoded. Utilities like [`ToArg`] will panic for `std` types if the input is not valid UTF-8.
+//! The statements are stored as strings, so inputs and outputs are expected to be UTF-8 encoded.
+//! [`ToArg`] will panic for `std` types like [`Path`](std::path::Path) if the input is not
+//! valid UTF-8. To handle this, there are two options:
+//! - [`TryToArg`] returns an [`Error`] instead, and the `try_` methods like
+//!   [`Ninja::try_rule`], [`RuleRef::try_build`] and [`BuildVariables::try_with`] take it,
+//!   so the caller can skip or report the bad path.
+//! - [`Bytes`] wraps bytes (and on Unix, `OsStr` and `Path`) that don't need to be valid UTF-8.
+//!   Use [`Ninja::write_bytes`] or [`Ninja::to_bytes`] to write them back exactly, since
+//!   [`Display`](core::fmt::Display) needs UTF-8.
 //!
 //! ## Args and lists
 //! All functions take implementation of [`ToArg`] as parameters.
@@ -130,6 +137,10 @@
 //! ```
 //! The example won't compile unless you enable the `thread-safe` feature.
 //!
+//! The statements end up in the order the threads added them, which can change between runs.
+//! Give each thread its own [`Ninja::section`], or use [`RenderOptions::canonical`],
+//! to get the same output every time.
+//!
 //! ## Escaping
 //! There is an [`escape`] function that can be used to escape strings
 //! according to [the behavior](https://ninja-build.org/manual.html#ref_lexer) of ninja.
@@ -152,6 +163,12 @@
 //! use ninja_writer::escape_build;
 //! assert_eq!(escape_build("foo: bar"), "foo$:$ bar");
 //! ```
+//! To not have to remember escaping, use [`Ninja::auto_escape`] to escape all paths
+//! passed to the builder methods, and [`Raw`] for paths that are already escaped.
+//!
+//! Values and paths can also be tagged with [`Literal`] (escaped when rendered),
+//! [`Raw`] (emitted as is) and [`VarRef`] (a variable reference), or a [`NinjaStr`]
+//! that mixes them, so text is escaped exactly once.
 //!
 //! ## Duplicated variables
 //! Duplicates are not checked, since ninja allows it.
@@ -168,9 +185,55 @@
 //! "###);
 //! ```
 //!
+//! ## Parsing
+//! Existing ninja files can be read with [`Ninja::parse`], then extended with the same API.
+//! Paths and values are kept escaped, so the output stays the same as the input
+//! (except for formatting).
+//! ```rust
+//! use ninja_writer::*;
+//!
+//! let ninja = Ninja::parse("rule cc\n  command = gcc -c $in -o $out\n").unwrap();
+//! ninja.defaults(["foo.o"]);
+//!
+//! assert_eq!(ninja.to_string(), r###"
+//! rule cc
+//!   command = gcc -c $in -o $out
+//!
+//! default foo.o
+//! "###);
+//!
+//! // parsing the output gives back the same statements
+//! assert_eq!(Ninja::parse(&ninja.to_string()).unwrap(), ninja);
+//! ```
+//! To edit a file by hand while keeping the comments and formatting, use [`Cst`] instead.
+//!
+//! With the `std` feature, a build made of multiple files can be loaded with [`LoadedNinja`],
+//! which follows the `include` and `subninja` statements.
+//!
 //! ## Order of statements
 //! The order of statements is preserved. Ninja's variables are expanded
 //! immediately except for in rules, so the order of statements does matter.
+//! Use [`Ninja::evaluate`] to see what the variables expand to, and [`Ninja::graph`]
+//! to query the dependencies between files.
+//!
+//! ## Checking
+//! [`Ninja::check`] reports what ninja would refuse to load (like undefined rules, duplicate
+//! outputs and dependency cycles) as errors, and likely mistakes (like unused rules and
+//! variables) as warnings.
+//!
+//! ## Compilation database
+//! [`Ninja::compile_commands_json`] creates `compile_commands.json` for the build edges
+//! of the given rules, without running `ninja -t compdb`.
+//!
+//! ## Quoting commands
+//! [`ShellCommand`] builds the `command` of a rule from a program and arguments,
+//! quoting each argument for POSIX `sh` or Windows and escaping it for ninja.
+//!
+//! ## Rendering
+//! `Display` writes the statements with a blank line between groups and 2-space indentation.
+//! Use [`Ninja::render`] with [`RenderOptions`] to change the indentation and blank lines,
+//! or to add a header. [`Ninja::write_to`] and [`Ninja::write_file`] write the file
+//! statement by statement, without building it in memory first.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -181,23 +244,67 @@ pub mod arg;
 #[doc(hidden)]
 pub mod build;
 #[doc(hidden)]
+pub mod bytes;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod compdb;
+#[doc(hidden)]
+pub mod cst;
+#[doc(hidden)]
+pub mod error;
+#[doc(hidden)]
+pub mod eval;
+#[doc(hidden)]
+pub mod graph;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod load;
+#[doc(hidden)]
 pub mod ninja;
 #[doc(hidden)]
+pub mod ninja_str;
+#[doc(hidden)]
+pub mod parse;
+#[doc(hidden)]
 pub mod pool;
 #[doc(hidden)]
+pub mod render;
+#[doc(hidden)]
 pub mod rule;
 #[doc(hidden)]
+pub mod shell;
+#[doc(hidden)]
 pub mod stmt;
 #[doc(hidden)]
 pub mod util;
 #[doc(hidden)]
+pub mod validate;
+#[doc(hidden)]
 pub mod variable;
+#[doc(hidden)]
+pub mod write;
 
 // Re-exports
-pub use arg::ToArg;
+pub use arg::{ToArg, TryToArg};
 pub use build::{Build, BuildRef, BuildVariables};
+pub use bytes::Bytes;
+pub use check::{Diagnostic, DiagnosticCode, Severity};
+pub use compdb::CompileCommand;
+pub use cst::{Cst, CstNode};
+pub use error::Error;
+pub use eval::{Edge, EdgeEnv, EvalError, Evaluation, Expanded};
+pub use graph::{DepKind, DependencyCycle, DuplicateOutput, Graph, OutputKind};
+#[cfg(feature = "std")]
+pub use load::{LoadError, LoadedNinja, LoadedStmt};
 pub use ninja::Ninja;
+pub use ninja_str::{Literal, NinjaStr, Raw, Segment, VarRef};
+pub use parse::{ParseError, ParseErrorKind};
 pub use pool::{Pool, PoolRef};
+pub use render::{BlankLines, RenderOptions};
 pub use rule::{Rule, RuleRef, RuleVariables};
+pub use shell::{Shell, ShellCommand};
+pub use stmt::{Stmt, StmtRef};
 pub use util::{escape, escape_build, escape_path};
+pub use validate::ValidationError;
 pub use variable::{Variable, Variables};
@@ -0,0 +1,273 @@
+//! Loading a build made of multiple files with `include` and `subninja`
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+
+use crate::eval::Evaluator;
+use crate::stmt::Stmt;
+use crate::util::RefCounted;
+use crate::{Evaluation, Ninja, ParseError};
+
+/// A build loaded from a root file, with `include` and `subninja` statements followed
+///
+/// The statements from all files are in one list, in the order that ninja sees them:
+/// the statements of an included file come right after the `include` (or `subninja`) statement.
+/// Each statement records the file it came from and the scope it is in.
+///
+/// # Scopes
+/// Scoping follows [ninja](https://ninja-build.org/manual.html#ref_scope):
+/// `include` shares the scope of the parent file, while `subninja` creates a child scope
+/// where variables and rules from the parent are visible, but not the other way around.
+/// The root file is in scope `0`.
+///
+/// # Paths
+/// The paths in `include` and `subninja` are evaluated with the variables in the current scope,
+/// and are relative to the base directory, which is normally the directory ninja runs in,
+/// not the directory of the including file.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let dir = std::env::temp_dir().join("ninja-writer-load-doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("build.ninja"), "include rules.ninja\nsubninja sub.ninja\n").unwrap();
+/// std::fs::write(dir.join("rules.ninja"), "rule cc\n  command = gcc -c $in -o $out\n").unwrap();
+/// std::fs::write(dir.join("sub.ninja"), "build foo.o: cc foo.c\n").unwrap();
+///
+/// let loaded = LoadedNinja::load(&dir, "build.ninja").unwrap();
+/// assert_eq!(loaded.files, [dir.join("build.ninja"), dir.join("rules.ninja"), dir.join("sub.ninja")]);
+/// assert_eq!(loaded.scopes, [None, Some(0)]);
+///
+/// let foo_o = &loaded.stmts[3];
+/// assert!(matches!(foo_o.stmt.as_ref(), Stmt::Build(_)));
+/// assert_eq!(loaded.files[foo_o.file], dir.join("sub.ninja"));
+/// assert_eq!(foo_o.scope, 1);
+/// ```
+#[derive(Debug)]
+pub struct LoadedNinja {
+    /// The directory that paths in `include` and `subninja` are relative to
+    pub base_dir: PathBuf,
+
+    /// The files that are loaded, starting with the root file
+    pub files: Vec<PathBuf>,
+
+    /// The parent of each scope. The root scope (`0`) has no parent.
+    pub scopes: Vec<Option<usize>>,
+
+    /// The statements from all files, in the order that ninja sees them
+    pub stmts: Vec<LoadedStmt>,
+}
+
+/// A statement in a [`LoadedNinja`]
+#[derive(Debug, Clone)]
+pub struct LoadedStmt {
+    /// The statement
+    pub stmt: RefCounted<Stmt>,
+    /// Index of the file in [`LoadedNinja::files`]
+    pub file: usize,
+    /// Index of the scope in [`LoadedNinja::scopes`]
+    pub scope: usize,
+}
+
+/// Error when loading a [`LoadedNinja`]
+#[derive(Debug)]
+pub enum LoadError {
+    /// A file cannot be read
+    Io(PathBuf, std::io::Error),
+    /// A file cannot be parsed
+    Parse(PathBuf, ParseError),
+    /// A file includes itself, directly or indirectly
+    Cycle(PathBuf),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            Self::Parse(path, error) => write!(f, "{}: {}", path.display(), error),
+            Self::Cycle(path) => write!(f, "{}: file includes itself", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, error) => Some(error),
+            Self::Parse(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl LoadedNinja {
+    /// Load the root file and all the files it includes.
+    ///
+    /// The root file is relative to the base directory as well
+    pub fn load(base_dir: impl AsRef<Path>, root: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let base_dir = base_dir.as_ref().to_path_buf();
+        let root = base_dir.join(root);
+        let mut loaded = Self {
+            base_dir,
+            files: Vec::new(),
+            scopes: vec![None],
+            stmts: Vec::new(),
+        };
+        let mut evaluator = Evaluator::new([None]);
+        loaded.load_file(root, 0, &mut evaluator, &mut Vec::new())?;
+        Ok(loaded)
+    }
+
+    /// Evaluate the variables of all the files
+    ///
+    /// The indices of the statements and scopes in the [`Evaluation`] are the same as
+    /// [`stmts`](Self::stmts) and [`scopes`](Self::scopes)
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let dir = std::env::temp_dir().join("ninja-writer-load-eval-doc");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("build.ninja"), "sub = sub\ncflags = -O2\nsubninja $sub.ninja\n").unwrap();
+    /// std::fs::write(dir.join("sub.ninja"), "cflags = $cflags -g\n").unwrap();
+    ///
+    /// let eval = LoadedNinja::load(&dir, "build.ninja").unwrap().evaluate();
+    /// assert_eq!(eval.variable("cflags"), Some("-O2"));
+    /// assert_eq!(eval.scope_variable(1, "cflags"), Some("-O2 -g"));
+    /// ```
+    pub fn evaluate(&self) -> Evaluation {
+        let mut evaluator = Evaluator::new(self.scopes.iter().copied());
+        for (i, stmt) in self.stmts.iter().enumerate() {
+            evaluator.push(&stmt.stmt, i, stmt.scope);
+        }
+        evaluator.finish()
+    }
+
+    /// Load one file into the scope
+    fn load_file(
+        &mut self,
+        path: PathBuf,
+        scope: usize,
+        evaluator: &mut Evaluator,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), LoadError> {
+        if stack.contains(&path) {
+            return Err(LoadError::Cycle(path));
+        }
+        let input = match std::fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => return Err(LoadError::Io(path, e)),
+        };
+        let ninja = match Ninja::parse(&input) {
+            Ok(ninja) => ninja,
+            Err(e) => return Err(LoadError::Parse(path, e)),
+        };
+        let file = self.files.len();
+        self.files.push(path.clone());
+        stack.push(path);
+        for stmt in ninja.stmts.inner().iter() {
+            evaluator.push(stmt, self.stmts.len(), scope);
+            self.stmts.push(LoadedStmt {
+                stmt: RefCounted::clone(stmt),
+                file,
+                scope,
+            });
+            match stmt.as_ref() {
+                Stmt::Include(include) => {
+                    let include = self.base_dir.join(evaluator.evaluate_in(scope, include));
+                    self.load_file(include, scope, evaluator, stack)?;
+                }
+                Stmt::Subninja(subninja) => {
+                    let subninja = self.base_dir.join(evaluator.evaluate_in(scope, subninja));
+                    let child = evaluator.add_scope(scope);
+                    self.scopes.push(Some(scope));
+                    self.load_file(subninja, child, evaluator, stack)?;
+                }
+                _ => {}
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Create a directory with the files for a test
+    fn setup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ninja-writer-test-load-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_scopes() {
+        let dir = setup(
+            "scopes",
+            &[
+                (
+                    "build.ninja",
+                    "x = 1\nsubninja a/a.ninja\nsubninja b$ b.ninja\n",
+                ),
+                (
+                    "a/a.ninja",
+                    "d = a\ninclude $d/inc.ninja\nsubninja ${d}/sub.ninja\n",
+                ),
+                ("a/inc.ninja", "y = 2\n"),
+                ("a/sub.ninja", "z = 3\n"),
+                ("b b.ninja", "w = 4\n"),
+            ],
+        );
+        let loaded = LoadedNinja::load(&dir, "build.ninja").unwrap();
+        assert_eq!(loaded.scopes, [None, Some(0), Some(1), Some(0)]);
+        let stmts: Vec<_> = loaded
+            .stmts
+            .iter()
+            .map(|s| (s.stmt.to_string(), s.file, s.scope))
+            .collect();
+        assert_eq!(
+            stmts,
+            [
+                ("x = 1\n".to_string(), 0, 0),
+                ("subninja a/a.ninja\n".to_string(), 0, 0),
+                ("d = a\n".to_string(), 1, 1),
+                ("include $d/inc.ninja\n".to_string(), 1, 1),
+                ("y = 2\n".to_string(), 2, 1),
+                ("subninja ${d}/sub.ninja\n".to_string(), 1, 1),
+                ("z = 3\n".to_string(), 3, 2),
+                ("subninja b$ b.ninja\n".to_string(), 0, 0),
+                ("w = 4\n".to_string(), 4, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = setup(
+            "errors",
+            &[
+                ("cycle.ninja", "include cycle2.ninja\n"),
+                ("cycle2.ninja", "subninja cycle.ninja\n"),
+                ("parse.ninja", "include missing.ninja\n  x = 1\n"),
+                ("missing.ninja", "include not_found.ninja\n"),
+            ],
+        );
+        let err = LoadedNinja::load(&dir, "cycle.ninja").unwrap_err();
+        assert!(matches!(err, LoadError::Cycle(p) if p == dir.join("cycle.ninja")));
+        let err = LoadedNinja::load(&dir, "parse.ninja").unwrap_err();
+        assert!(
+            matches!(err, LoadError::Parse(p, e) if p == dir.join("parse.ninja") && e.line == 2)
+        );
+        let err = LoadedNinja::load(&dir, "missing.ninja").unwrap_err();
+        assert!(matches!(err, LoadError::Io(p, _) if p == dir.join("not_found.ninja")));
+    }
+}
@@ -1,11 +1,19 @@
 //! Implementation of top-level stuff
 
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::{Display, Formatter, Result};
 
+use crate::arg::to_path_args;
+use crate::eval::evaluate_list;
+use crate::parse::{ParseError, parse_into};
 use crate::stmt::{Stmt, StmtRef};
-use crate::util::{AddOnlyVec, RefCounted};
-use crate::{Build, BuildRef, Pool, PoolRef, Rule, RuleRef, ToArg, Variable};
+use crate::util::{RefCounted, SharedVec, WeakRef};
+use crate::{
+    Build, BuildRef, Error, EvalError, Evaluation, Pool, PoolRef, Rule, RuleRef, ToArg, TryToArg,
+    Variable,
+};
 
 /// The main entry point for writing a ninja file.
 ///
@@ -14,10 +22,64 @@ use crate::{Build, BuildRef, Pool, PoolRef, Rule, RuleRef, ToArg, Variable};
 #[derive(Debug)]
 pub struct Ninja {
     /// The list of statements
-    pub stmts: RefCounted<AddOnlyVec<RefCounted<Stmt>>>,
+    pub stmts: RefCounted<SharedVec<RefCounted<Stmt>>>,
 
     /// The built-in phony rule,
     pub phony: Rule,
+
+    /// If paths added with the builder methods are escaped automatically.
+    ///
+    /// See [`auto_escape`](Self::auto_escape)
+    pub auto_escape: bool,
+
+    /// The sections of this ninja file, by key. See [`section`](Self::section)
+    pub sections: RefCounted<SharedVec<(String, RefCounted<Ninja>)>>,
+
+    /// The width to wrap long lines at, if any.
+    ///
+    /// See [`wrap_width`](Self::wrap_width)
+    pub wrap_width: Option<usize>,
+
+    /// The ninja file this is a section of, if any
+    pub(crate) root: Option<Root>,
+}
+
+/// The statements of the ninja file at the top of the sections, see [`Ninja::section`]
+#[derive(Debug, Clone)]
+pub(crate) struct Root {
+    stmts: RefCounted<SharedVec<RefCounted<Stmt>>>,
+    /// Weak, since the sections refer to the root
+    sections: WeakRef<SharedVec<(String, RefCounted<Ninja>)>>,
+}
+
+impl Root {
+    /// Get the statements of the root ninja file and its sections, in the rendered order
+    pub fn all_stmts(&self) -> Vec<RefCounted<Stmt>> {
+        match self.sections.upgrade() {
+            Some(sections) => stmt_lists(&self.stmts, &sections)
+                .iter()
+                .flat_map(|list| list.inner().clone())
+                .collect(),
+            // the root ninja file is dropped with its sections, so only its statements are left
+            None => self.stmts.inner().clone(),
+        }
+    }
+}
+
+impl PartialEq for Ninja {
+    /// Compare the statements that are rendered, including the ones in sections.
+    ///
+    /// Options like [`auto_escape`](Self::auto_escape) and [`wrap_width`](Self::wrap_width)
+    /// are not compared, so parsing the output gives back an equal ninja file.
+    fn eq(&self, other: &Self) -> bool {
+        let rendered = |ninja: &Ninja| {
+            ninja
+                .all_stmts()
+                .into_iter()
+                .filter(|stmt| !stmt.is_empty())
+        };
+        rendered(self).eq(rendered(other))
+    }
 }
 
 impl Default for Ninja {
@@ -32,9 +94,121 @@ impl Ninja {
         Self {
             phony: Rule::new("phony", ""),
             stmts: Default::default(),
+            auto_escape: false,
+            sections: Default::default(),
+            wrap_width: None,
+            root: None,
         }
     }
 
+    /// Escape paths automatically when they are added with the builder methods
+    ///
+    /// Outputs and inputs of build edges, as well as paths in `default`, `subninja` and `include`
+    /// are escaped with [`escape_build`](crate::escape_build), so paths with spaces, `:` or `$`
+    /// can be passed as is. Colons are escaped in inputs too, since they end paths anywhere
+    /// on the `build` line. Use [`Raw`](crate::Raw) to pass a path that is already escaped,
+    /// for example to reference a variable.
+    ///
+    /// Rules added to this ninja file, and build edges using them, will have auto-escaping enabled.
+    /// Variables are not affected.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new().auto_escape();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// cc.build(["my files/foo.o"]).with(["my files/foo.c", "C:/include/foo.h"]);
+    /// ninja.phony(["all"]).with(["my files/foo.o"]);
+    /// ninja.defaults([Raw("$all")]);
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = gcc -c $in -o $out
+    ///
+    /// build my$ files/foo.o: cc my$ files/foo.c C$:/include/foo.h
+    /// build all: phony my$ files/foo.o
+    ///
+    /// default $all
+    /// "###);
+    /// ```
+    pub fn auto_escape(mut self) -> Self {
+        self.auto_escape = true;
+        self.phony.auto_escape = true;
+        self
+    }
+
+    /// Wrap lines longer than `width` characters when rendering
+    ///
+    /// Like Python's `ninja_syntax`, long lines are broken at spaces between list items
+    /// with `$` continuations, and the next line is indented further. Escaped spaces
+    /// are never broken, and a line with no space to break at is left as is.
+    /// This applies to build lines, `default` lines and variable values, but not comments.
+    /// [`render`](Self::render) wraps at this width as well, unless
+    /// [`RenderOptions::wrap_width`](crate::RenderOptions::wrap_width) is set.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new().wrap_width(30);
+    /// let link = ninja.rule("link", "gcc $in -o $out");
+    /// link.build(["app"]).with(["main.o", "foo$ bar.o", "util.o", "more.o"]);
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule link
+    ///   command = gcc $in -o $out
+    ///
+    /// build app: link main.o $
+    ///     foo$ bar.o util.o more.o
+    /// "###);
+    /// ```
+    pub fn wrap_width(mut self, width: usize) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    /// Parse an existing ninja file into statements
+    ///
+    /// Paths and values are kept escaped, the same way they are stored when using the builder API,
+    /// and blank lines are discarded. Top-level comments are kept as comment statements.
+    /// Include and subninja statements are not followed (see `LoadedNinja` for that).
+    /// See [`Cst`](crate::Cst) for parsing without losing comments and formatting.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::parse(r###"
+    /// cflags = -Wall
+    /// rule cc
+    ///   command = gcc $cflags -c $in -o $out
+    ///
+    /// build foo$ bar.o: cc foo$ bar.c | $
+    ///     foo.h
+    ///   cflags = -O2
+    /// "###).unwrap();
+    ///
+    /// // more statements can be added
+    /// ninja.phony(["all"]).with(["foo$ bar.o"]);
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// cflags = -Wall
+    ///
+    /// rule cc
+    ///   command = gcc $cflags -c $in -o $out
+    ///
+    /// build foo$ bar.o: cc foo$ bar.c | foo.h
+    ///   cflags = -O2
+    /// build all: phony foo$ bar.o
+    /// "###);
+    /// ```
+    pub fn parse(input: &str) -> core::result::Result<Self, ParseError> {
+        let ninja = Self::new();
+        parse_into(&ninja, input, &[])?;
+        Ok(ninja)
+    }
+
     /// Create a new rule with the given name and command and add it to this ninja file.
     ///
     /// The returned [`RuleRef`] can be used to configure the rule and build edges
@@ -58,6 +232,42 @@ impl Ninja {
         Rule::new(name, command).add_to(self)
     }
 
+    /// Create a new rule with the given name and command and add it to this ninja file,
+    /// or return an error if the name or command can't be converted.
+    ///
+    /// See [`TryToArg`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::path::Path;
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let compiler = Path::new("/usr/bin/gcc");
+    /// let cc = ninja.try_rule("cc", compiler).unwrap();
+    /// cc.try_build([Path::new("foo.o")]).unwrap()
+    ///     .try_with([Path::new("foo.c")]).unwrap();
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = /usr/bin/gcc
+    ///
+    /// build foo.o: cc foo.c
+    /// "###);
+    /// # }
+    /// ```
+    pub fn try_rule(
+        &self,
+        name: impl TryToArg,
+        command: impl TryToArg,
+    ) -> core::result::Result<RuleRef, Error> {
+        let name = name.try_to_arg()?;
+        let command = command.try_to_arg()?;
+        Ok(self.rule(name, command))
+    }
+
     /// Add a new build edge with the `phony` rule, used for aliasing
     ///
     /// See <https://ninja-build.org/manual.html#_the_literal_phony_literal_rule>
@@ -125,8 +335,62 @@ impl Ninja {
         self
     }
 
+    /// Set a top-level variable, replacing the variables with the same name
+    /// instead of adding another one
+    ///
+    /// The first variable with the name is replaced, and the others are removed.
+    /// If there is none, the variable is added at the end.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::Ninja;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.variable("cflags", "-O0");
+    /// ninja.variable("cc", "gcc");
+    /// ninja.set_variable("cflags", "-O2");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// cflags = -O2
+    /// cc = gcc
+    /// "###);
+    /// ```
+    pub fn set_variable(&self, name: impl ToArg, value: impl ToArg) -> &Self {
+        let variable = Variable::new(name, value);
+        let name = variable.name.clone();
+        self.stmts.set(
+            |stmt| matches!(stmt.as_ref(), Stmt::Variable(v) if v.name == name),
+            RefCounted::new(Stmt::Variable(variable)),
+        );
+        self
+    }
+
+    /// Remove the top-level variables with the name
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::Ninja;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.variable("cflags", "-O0");
+    /// ninja.variable("cc", "gcc");
+    /// ninja.remove_variable("cflags");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// cc = gcc
+    /// "###);
+    /// ```
+    pub fn remove_variable(&self, name: impl AsRef<str>) -> &Self {
+        let name = name.as_ref();
+        self.stmts
+            .retain(|stmt| !matches!(stmt.as_ref(), Stmt::Variable(v) if v.name == name));
+        self
+    }
+
     /// Add a default statement
     ///
+    /// Nothing is added if there are no outputs, since ninja rejects an empty `default`.
+    ///
     /// See <https://ninja-build.org/manual.html#_default_target_statements>
     ///
     /// # Example
@@ -136,6 +400,7 @@ impl Ninja {
     /// let ninja = Ninja::new();
     /// ninja.defaults(["foo", "bar"]);
     /// ninja.defaults(["baz"]);
+    /// ninja.defaults(Vec::<String>::new());
     ///
     /// assert_eq!(ninja.to_string(), r###"
     /// default foo bar
@@ -143,9 +408,10 @@ impl Ninja {
     /// "###);
     /// ```
     pub fn defaults(&self, outputs: impl IntoIterator<Item = impl ToArg>) -> &Self {
-        self.stmts.add_rc(Stmt::Default(
-            outputs.into_iter().map(|s| s.to_arg()).collect(),
-        ));
+        let outputs: Vec<_> = to_path_args(outputs, self.auto_escape).collect();
+        if !outputs.is_empty() {
+            self.stmts.add_rc(Stmt::Default(outputs));
+        }
         self
     }
 
@@ -164,7 +430,8 @@ impl Ninja {
     /// "###);
     /// ```
     pub fn subninja(&self, path: impl ToArg) -> &Self {
-        self.stmts.add_rc(Stmt::Subninja(path.to_arg()));
+        let path = to_path_args([path], self.auto_escape).collect();
+        self.stmts.add_rc(Stmt::Subninja(path));
         self
     }
 
@@ -188,56 +455,149 @@ impl Ninja {
     /// "###);
     /// ```
     pub fn include(&self, path: impl ToArg) -> &Self {
-        self.stmts.add_rc(Stmt::Include(path.to_arg()));
+        let path = to_path_args([path], self.auto_escape).collect();
+        self.stmts.add_rc(Stmt::Include(path));
         self
     }
 
+    /// Evaluate the variables in this ninja file
+    ///
+    /// `include` and `subninja` statements are not followed.
+    /// See [`Evaluation`] for details and examples.
+    #[inline]
+    pub fn evaluate(&self) -> Evaluation {
+        evaluate_list(&self.all_stmts())
+    }
+
+    /// Get the expanded commands of all build edges, similar to `ninja -t commands`
+    ///
+    /// The commands are in the order of the build statements instead of the order they would
+    /// run in. `phony` edges don't have a command and are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc $cflags -c $in -o $out");
+    /// cc.build(["foo.o"]).with(["foo.c"]);
+    /// cc.build(["bar.o"]).with(["bar.c"]).variable("cflags", "-O2");
+    /// ninja.phony(["all"]).with(["foo.o", "bar.o"]);
+    ///
+    /// assert_eq!(ninja.commands().unwrap(), [
+    ///     "gcc  -c foo.c -o foo.o",
+    ///     "gcc -O2 -c bar.c -o bar.o",
+    /// ]);
+    /// ```
+    pub fn commands(&self) -> core::result::Result<Vec<String>, EvalError> {
+        self.evaluate()
+            .edges()
+            .filter(|edge| edge.rule().is_some())
+            .map(|edge| edge.variable("command"))
+            .collect()
+    }
+
+    /// Get the section with the given key, creating it if it doesn't exist
+    ///
+    /// A section has its own list of statements, and is rendered after the statements of this
+    /// ninja file. Sections are rendered in the order of their keys, while the statements in
+    /// each section keep the order they were added in. This makes the output reproducible when
+    /// multiple threads add statements, as long as each thread uses its own section.
+    /// Keys are compared as strings, so numbers should be padded (like `"007"`) to sort
+    /// numerically.
+    ///
+    /// The statements in sections are included when evaluating, checking and validating
+    /// this ninja file, and the statement indices count them in the rendered order.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    ///
+    /// let b = ninja.section("b");
+    /// Build::new(&cc, ["b.o"]).add_to(&b).with(["b.c"]);
+    /// let a = ninja.section("a");
+    /// Build::new(&cc, ["a.o"]).add_to(&a).with(["a.c"]);
+    /// ninja.section("b").phony(["b"]).with(["b.o"]);
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = gcc -c $in -o $out
+    ///
+    /// build a.o: cc a.c
+    /// build b.o: cc b.c
+    /// build b: phony b.o
+    /// "###);
+    /// ```
+    pub fn section(&self, key: impl Into<String>) -> RefCounted<Ninja> {
+        let key = key.into();
+        let (_, section) = self.sections.find_or_add(
+            |(k, _)| *k == key,
+            || {
+                let mut section = match self.auto_escape {
+                    true => Ninja::new().auto_escape(),
+                    false => Ninja::new(),
+                };
+                section.root = Some(self.root());
+                (key.clone(), RefCounted::new(section))
+            },
+        );
+        section
+    }
+
+    /// Get the statement lists of this ninja file and its sections, in the rendered order
+    #[inline]
+    pub(crate) fn stmt_lists(&self) -> Vec<RefCounted<SharedVec<RefCounted<Stmt>>>> {
+        stmt_lists(&self.stmts, &self.sections)
+    }
+
+    /// Get the root of the sections this ninja file is in, which is itself if it is
+    /// not a section
+    pub(crate) fn root(&self) -> Root {
+        self.root.clone().unwrap_or_else(|| Root {
+            stmts: RefCounted::clone(&self.stmts),
+            sections: RefCounted::downgrade(&self.sections),
+        })
+    }
+
+    /// Get the statements of this ninja file and its sections, in the rendered order
+    pub(crate) fn all_stmts(&self) -> Vec<RefCounted<Stmt>> {
+        self.stmt_lists()
+            .iter()
+            .flat_map(|list| list.inner().clone())
+            .collect()
+    }
+
     /// Internal function to add a statement
     pub(crate) fn add_stmt(&self, stmt: Stmt) -> StmtRef {
         StmtRef {
             stmt: self.stmts.add_rc(stmt),
             list: RefCounted::clone(&self.stmts),
+            root: self.root(),
         }
     }
 }
 
+/// Get the list of statements and the lists of the sections, in the rendered order
+fn stmt_lists(
+    stmts: &RefCounted<SharedVec<RefCounted<Stmt>>>,
+    sections: &SharedVec<(String, RefCounted<Ninja>)>,
+) -> Vec<RefCounted<SharedVec<RefCounted<Stmt>>>> {
+    let mut lists = Vec::from([RefCounted::clone(stmts)]);
+    let mut sections = sections.inner().clone();
+    sections.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (_, section) in sections {
+        lists.extend(section.stmt_lists());
+    }
+    lists
+}
+
 impl Display for Ninja {
+    #[inline]
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let list = &self.stmts.inner();
-        if list.is_empty() {
-            return Ok(());
-        }
-        let mut last = 0;
-        for stmt in list.iter() {
-            let stmt = stmt.as_ref();
-            // have a blank line between statement types and between rules
-            let next = stmt.ordinal() + 1;
-            if matches!(stmt, Stmt::Rule(_)) || next != last {
-                writeln!(f)?;
-            }
-            last = next;
-
-            match stmt {
-                Stmt::Rule(rule) => rule.fmt(f)?,
-                Stmt::Build(build) => build.fmt(f)?,
-                Stmt::Pool(pool) => pool.fmt(f)?,
-                Stmt::Comment(comment) => writeln!(f, "# {}", comment)?,
-                Stmt::Variable(variable) => {
-                    variable.fmt(f)?;
-                    writeln!(f)?;
-                }
-                Stmt::Default(outputs) => {
-                    write!(f, "default")?;
-                    for output in outputs {
-                        write!(f, " {}", output)?;
-                    }
-                    writeln!(f)?;
-                }
-                Stmt::Subninja(path) => writeln!(f, "subninja {}", path)?,
-                Stmt::Include(path) => writeln!(f, "include {}", path)?,
-            }
-        }
-        Ok(())
+        self.write_to_fmt(f)
     }
 }
 
@@ -252,5 +612,155 @@ mod test {
         assert_eq!(ninja.to_string(), "");
     }
 
+    #[test]
+    fn test_auto_escape() {
+        use crate::{BuildVariables, Raw};
+
+        let ninja = Ninja::new().auto_escape();
+        let rule = ninja.rule("r", "...");
+        rule.build(["a b"])
+            .output_implicit(["c:d"])
+            .with(["$e"])
+            .with_implicit(["f g"])
+            .with_order_only([Raw("$h")])
+            .validations(["i j"]);
+        ninja.subninja("k l.ninja").include("m$.ninja");
+        assert_eq!(
+            ninja.to_string(),
+            "\nrule r\n  command = ...\n\nbuild a$ b | c$:d: r $$e | f$ g || $h |@ i$ j\n\nsubninja k$ l.ninja\n\ninclude m$$.ninja\n"
+        );
+
+        // rules added to a ninja file without auto-escape are not escaped
+        let ninja = Ninja::new();
+        ninja.rule("r", "...").build(["a b"]);
+        let rule = Rule::new("s", "...").auto_escape().add_to(&ninja);
+        rule.build(["a b"]);
+        assert_eq!(
+            ninja.to_string(),
+            "\nrule r\n  command = ...\n\nbuild a b: r\n\nrule s\n  command = ...\n\nbuild a$ b: s\n"
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        use crate::DiagnosticCode;
+
+        let ninja = Ninja::new();
+        let section = ninja.section("1");
+        section.rule("r", "cmd $out").build(["a"]);
+        section.section("0").defaults(["a"]);
+        Build::new(&Rule::new("missing", ""), ["b"]).add_to(&ninja.section("0"));
+        ninja.comment("c");
+        assert_eq!(
+            ninja.to_string(),
+            "\n# c\n\nbuild b: missing\n\nrule r\n  command = cmd $out\n\nbuild a: r\n\ndefault a\n"
+        );
+        assert_eq!(ninja.commands().unwrap(), ["cmd a"]);
+        let diagnostics = ninja.check();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::UndefinedRule);
+        assert_eq!(diagnostics[0].index, 1);
+        // the statement reference adds to the section
+        diagnostics[0].stmt.add(Stmt::Comment("fixme".into()));
+        assert_eq!(ninja.section("0").stmts.inner().len(), 2);
+    }
+
+    #[test]
+    fn test_sections_expand() {
+        use crate::{BuildVariables, Variables};
+
+        let ninja = Ninja::new();
+        ninja.variable("flags", "-O2");
+        let rule = ninja.rule("cc", "cc $flags $in -o $out");
+        let section = ninja.section("a").section("b");
+        let build = Build::new(&rule, ["a.o"]).add_to(&section).with(["a.c"]);
+        let other = Build::new(&rule, ["b.o"])
+            .add_to(&ninja.section("c"))
+            .with(["b.c"])
+            .variable("flags", "-g");
+        assert_eq!(build.expand().unwrap().command, "cc -O2 a.c -o a.o");
+        assert_eq!(other.expand().unwrap().command, "cc -g b.c -o b.o");
+        assert_eq!(
+            ninja.commands().unwrap(),
+            ["cc -O2 a.c -o a.o", "cc -g b.c -o b.o"]
+        );
+
+        // the statements of the root are kept after it is dropped
+        drop(ninja);
+        assert_eq!(build.expand().unwrap().command, "cc -O2 a.c -o a.o");
+    }
+
+    #[cfg(feature = "thread-safe")]
+    #[test]
+    fn test_sections_threads() {
+        use alloc::format;
+        use std::sync::Arc;
+
+        let render = || {
+            let ninja = Arc::new(Ninja::new());
+            let rule = ninja.rule("r", "cmd");
+            let threads: Vec<_> = (0..4)
+                .map(|i| {
+                    let ninja = Arc::clone(&ninja);
+                    let rule = rule.clone();
+                    std::thread::spawn(move || {
+                        let section = ninja.section(format!("{}", i));
+                        for j in 0..50 {
+                            Build::new(&rule, [format!("{}-{}", i, j)]).add_to(&section);
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+            ninja.to_string()
+        };
+        let expected = render();
+        assert!(expected.contains("build 0-49: r\nbuild 1-0: r\n"));
+        for _ in 0..5 {
+            assert_eq!(render(), expected);
+        }
+    }
+
+    #[test]
+    fn test_empty_paths() {
+        use crate::BuildVariables;
+
+        let ninja = Ninja::new();
+        let rule = ninja.rule("r", "cmd");
+        rule.build(Vec::<String>::new()).with(["a"]);
+        rule.build(Vec::<String>::new()).output_implicit(["b"]);
+        ninja.defaults(Vec::<String>::new());
+        assert_eq!(
+            ninja.to_string(),
+            "\nrule r\n  command = cmd\n\nbuild | b: r\n"
+        );
+        assert!(Ninja::parse(&ninja.to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_remove() {
+        use crate::{BuildVariables, Variables};
+
+        let ninja = Ninja::new().auto_escape();
+        let rule = ninja.rule("r", "cmd $in > $out");
+        let build = rule
+            .build(["a"])
+            .with(["b c", "d"])
+            .variable("x", "1")
+            .set_variable("x", "2")
+            .remove_input("b c");
+        assert!(build.remove());
+        assert_eq!(ninja.to_string(), "\nrule r\n  command = cmd $in > $out\n");
+        assert_eq!(build.expand().unwrap().command, "cmd d > a");
+        assert_eq!(build.to_string(), "build a: r d\n  x = 2\n");
+
+        let pool = ninja.pool("p", 1);
+        assert!(pool.remove());
+        assert!(rule.remove());
+        assert_eq!(ninja.to_string(), "");
+    }
+
     // doc tests should give enough coverage
 }
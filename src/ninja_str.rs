@@ -0,0 +1,512 @@
+//! Tagged ninja text that knows which parts need escaping
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result};
+
+use crate::bytes::encode_bytes;
+use crate::parse::is_ident_char;
+use crate::{ToArg, escape, escape_build};
+
+/// A part of a [`NinjaStr`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Text that is already escaped, emitted as is
+    Raw(String),
+    /// Text that is escaped when rendered
+    Literal(String),
+    /// A reference to a variable, rendered as `$name` or `${name}`
+    VarRef(String),
+    /// Bytes that are not valid UTF-8, from [`Bytes`](crate::Bytes). They are not escaped.
+    Bytes(Vec<u8>),
+}
+
+/// Ninja text made of raw text, literal text and variable references
+///
+/// The text is escaped when it is rendered, so escaping happens exactly once.
+/// How literal text is escaped depends on where it is used: in values, only `$`
+/// and newlines are escaped (like [`escape`]), while in paths, spaces and `:`
+/// are escaped as well (like [`escape_build`]).
+///
+/// Two strings are equal if they render the same in a value, ignoring line
+/// continuations (`$` followed by a newline and indentation), which ninja skips.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// let cflags = NinjaStr::new()
+///     .var("cflags")
+///     .literal(" -DPRICE=$5")
+///     .raw(" $$HOME");
+/// ninja.variable("cflags", cflags);
+/// ninja.variable("out", VarRef("builddir"));
+/// ninja.variable("msg", Literal("costs $5"));
+///
+/// assert_eq!(ninja.to_string(), r###"
+/// cflags = $cflags -DPRICE=$$5 $$HOME
+/// out = $builddir
+/// msg = costs $$5
+/// "###);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NinjaStr {
+    segments: Vec<Segment>,
+}
+
+impl NinjaStr {
+    /// Create an empty string
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append text that is already escaped
+    pub fn raw(mut self, text: impl ToArg) -> Self {
+        self.segments.push(Segment::Raw(text.to_arg()));
+        self
+    }
+
+    /// Append text that will be escaped
+    ///
+    /// Bytes that are not valid UTF-8 (see [`Bytes`](crate::Bytes)) are kept as bytes.
+    pub fn literal(mut self, text: impl ToArg) -> Self {
+        let text = text.to_ninja_str();
+        if !text.segments.iter().any(|s| matches!(s, Segment::Bytes(_))) {
+            self.segments.push(Segment::Literal(text.to_arg()));
+            return self;
+        }
+        for segment in text.segments {
+            let segment = match segment {
+                Segment::Bytes(bytes) => Segment::Bytes(bytes),
+                segment => Segment::Literal(
+                    NinjaStr {
+                        segments: Vec::from([segment]),
+                    }
+                    .to_arg(),
+                ),
+            };
+            self.segments.push(segment);
+        }
+        self
+    }
+
+    /// Append a reference to a variable
+    ///
+    /// # Panic
+    /// This will panic if the name is not a valid variable name (`[a-zA-Z0-9_.-]+`),
+    /// since ninja can't parse a reference to it.
+    pub fn var(mut self, name: impl ToArg) -> Self {
+        let name = name.to_arg();
+        assert!(
+            !name.is_empty() && name.bytes().all(is_ident_char),
+            "invalid variable name: {name:?}"
+        );
+        self.segments.push(Segment::VarRef(name));
+        self
+    }
+
+    /// Append bytes that are not valid UTF-8
+    pub(crate) fn bytes(mut self, bytes: Vec<u8>) -> Self {
+        self.segments.push(Segment::Bytes(bytes));
+        self
+    }
+
+    /// Append the segments of another string
+    pub fn append(mut self, other: impl ToArg) -> Self {
+        self.segments.extend(other.to_ninja_str().segments);
+        self
+    }
+
+    /// Get the segments of the string
+    #[inline]
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Get the escaped text for using in a value
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let s = NinjaStr::new().literal("a: $b").var("c").literal("d");
+    /// assert_eq!(s.escaped(), "a: $$b${c}d");
+    /// ```
+    #[inline]
+    pub fn escaped(&self) -> Cow<'_, str> {
+        self.render(false)
+    }
+
+    /// Get the escaped text for using as a path
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let s = NinjaStr::new().var("dir").literal("/a: $b");
+    /// assert_eq!(s.escaped_path(), "$dir/a$:$ $$b");
+    /// ```
+    #[inline]
+    pub fn escaped_path(&self) -> Cow<'_, str> {
+        self.render(true)
+    }
+
+    /// Render the segments, escaping literals for a path or a value
+    fn render(&self, path: bool) -> Cow<'_, str> {
+        match self.segments.as_slice() {
+            [] => return Cow::Borrowed(""),
+            [Segment::Raw(raw)] => return Cow::Borrowed(raw),
+            _ => {}
+        }
+        let mut output = String::new();
+        // variable references are written when the next character is known
+        let mut pending: Option<&str> = None;
+        for segment in &self.segments {
+            let text = match segment {
+                Segment::Raw(raw) => Cow::Borrowed(raw.as_str()),
+                Segment::Literal(literal) if path => escape_build(literal),
+                // leading spaces of a value are skipped by ninja
+                Segment::Literal(literal) if output.is_empty() && pending.is_none() => {
+                    match literal.strip_prefix(' ') {
+                        Some(rest) => Cow::Owned(format!("$ {}", escape(rest))),
+                        None => escape(literal),
+                    }
+                }
+                Segment::Literal(literal) => escape(literal),
+                Segment::Bytes(bytes) => encode_bytes(bytes),
+                Segment::VarRef(name) => {
+                    if let Some(pending) = pending.replace(name) {
+                        write_var(&mut output, pending, Some('$'));
+                    }
+                    continue;
+                }
+            };
+            let Some(next) = text.chars().next() else {
+                continue;
+            };
+            if let Some(pending) = pending.take() {
+                write_var(&mut output, pending, Some(next));
+            }
+            output.push_str(&text);
+        }
+        if let Some(pending) = pending {
+            write_var(&mut output, pending, None);
+        }
+        Cow::Owned(output)
+    }
+
+    /// Convert to the escaped text for a path, without copying if possible
+    pub(crate) fn into_path(self) -> String {
+        match self.render(true) {
+            Cow::Owned(output) => output,
+            Cow::Borrowed(_) => match self.segments.into_iter().next() {
+                Some(Segment::Raw(raw)) => raw,
+                _ => String::new(),
+            },
+        }
+    }
+}
+
+/// Check if a character can be in a simple `$name` reference
+fn is_simple_var_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Write a variable reference, using `${name}` if `$name` would be ambiguous
+fn write_var(output: &mut String, name: &str, next: Option<char>) {
+    let simple = !name.is_empty()
+        && name.chars().all(is_simple_var_char)
+        && !next.is_some_and(is_simple_var_char);
+    output.push('$');
+    if simple {
+        output.push_str(name);
+    } else {
+        output.push('{');
+        output.push_str(name);
+        output.push('}');
+    }
+}
+
+impl Display for NinjaStr {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(&self.escaped())
+    }
+}
+
+/// Remove line continuations (`$` followed by a newline and indentation) from escaped text
+fn skip_continuations(text: &str) -> Cow<'_, str> {
+    if !text.contains("$\n") {
+        return Cow::Borrowed(text);
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        output.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        rest = match after.chars().next() {
+            Some('\n') => after[1..].trim_start_matches(' '),
+            Some(c) => {
+                output.push('$');
+                output.push(c);
+                &after[c.len_utf8()..]
+            }
+            None => {
+                output.push('$');
+                after
+            }
+        };
+    }
+    output.push_str(rest);
+    Cow::Owned(output)
+}
+
+impl PartialEq for NinjaStr {
+    fn eq(&self, other: &Self) -> bool {
+        skip_continuations(&self.escaped()) == skip_continuations(&other.escaped())
+    }
+}
+
+impl PartialEq<str> for NinjaStr {
+    fn eq(&self, other: &str) -> bool {
+        skip_continuations(&self.escaped()) == skip_continuations(other)
+    }
+}
+
+impl PartialEq<&str> for NinjaStr {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl From<String> for NinjaStr {
+    /// Create a string from text that is already escaped
+    fn from(raw: String) -> Self {
+        Self::new().raw(raw)
+    }
+}
+
+impl From<&str> for NinjaStr {
+    /// Create a string from text that is already escaped
+    fn from(raw: &str) -> Self {
+        Self::new().raw(raw)
+    }
+}
+
+impl ToArg for NinjaStr {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.escaped().into_owned()
+    }
+
+    #[inline]
+    fn to_path_arg(self) -> String {
+        self.into_path()
+    }
+
+    #[inline]
+    fn to_ninja_str(self) -> NinjaStr {
+        self
+    }
+}
+
+impl ToArg for &NinjaStr {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.escaped().into_owned()
+    }
+
+    #[inline]
+    fn to_path_arg(self) -> String {
+        self.escaped_path().into_owned()
+    }
+
+    #[inline]
+    fn to_ninja_str(self) -> NinjaStr {
+        self.clone()
+    }
+}
+
+/// Wrapper for an argument that is already escaped, so it is emitted as is.
+///
+/// This is useful for referencing variables in paths when auto-escaping is enabled.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new().auto_escape();
+/// let cp = ninja.rule("cp", "cp $in $out");
+/// cp.build([Raw("$builddir/foo$ bar")]).with(["foo bar"]);
+///
+/// assert_eq!(ninja.to_string(), r###"
+/// rule cp
+///   command = cp $in $out
+///
+/// build $builddir/foo$ bar: cp foo$ bar
+/// "###);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Raw<T>(pub T);
+
+impl<T: ToArg> ToArg for Raw<T> {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.0.to_arg()
+    }
+
+    #[inline]
+    fn to_path_arg(self) -> String {
+        self.0.to_arg()
+    }
+
+    #[inline]
+    fn to_ninja_str(self) -> NinjaStr {
+        NinjaStr::new().raw(self.0)
+    }
+}
+
+/// Wrapper for literal text, which is escaped when rendered
+///
+/// In paths, spaces and `:` are escaped as well, even when auto-escaping is not enabled.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// let echo = ninja.rule("echo", "echo $msg > $out");
+/// echo.build([Literal("hello world.txt")])
+///     .variable("msg", Literal("costs $5: cheap"));
+///
+/// assert_eq!(ninja.to_string(), r###"
+/// rule echo
+///   command = echo $msg > $out
+///
+/// build hello$ world.txt: echo
+///   msg = costs $$5: cheap
+/// "###);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Literal<T>(pub T);
+
+impl<T: ToArg> ToArg for Literal<T> {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.to_ninja_str().to_arg()
+    }
+
+    #[inline]
+    fn to_path_arg(self) -> String {
+        self.to_ninja_str().into_path()
+    }
+
+    #[inline]
+    fn to_ninja_str(self) -> NinjaStr {
+        NinjaStr::new().literal(self.0)
+    }
+}
+
+/// Wrapper for a reference to a variable, which is rendered as `$name` or `${name}`
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// ninja.variable("builddir", "out");
+/// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+/// cc.build([NinjaStr::new().var("builddir").literal("/foo.o")])
+///     .with(["foo.c"])
+///     .variable("cflags", VarRef("cflags.debug"));
+///
+/// assert_eq!(ninja.to_string(), r###"
+/// builddir = out
+///
+/// rule cc
+///   command = gcc -c $in -o $out
+///
+/// build $builddir/foo.o: cc foo.c
+///   cflags = ${cflags.debug}
+/// "###);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarRef<T>(pub T);
+
+impl<T: ToArg> ToArg for VarRef<T> {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.to_ninja_str().to_arg()
+    }
+
+    #[inline]
+    fn to_path_arg(self) -> String {
+        self.to_ninja_str().into_path()
+    }
+
+    #[inline]
+    fn to_ninja_str(self) -> NinjaStr {
+        NinjaStr::new().var(self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_var_braces() {
+        let s = NinjaStr::new()
+            .var("a")
+            .var("b")
+            .literal("")
+            .literal("-c")
+            .var("d")
+            .raw(".e")
+            .var("f.g")
+            .var("h");
+        assert_eq!(s.to_string(), "$a${b}-c$d.e${f.g}$h");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid variable name")]
+    fn test_var_empty() {
+        let _ = NinjaStr::new().var("");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid variable name")]
+    fn test_var_space() {
+        let _ = VarRef("a b").to_arg();
+    }
+
+    #[test]
+    fn test_eq() {
+        assert_eq!(NinjaStr::new().literal("a b$"), "a b$$");
+        assert_eq!(
+            NinjaStr::new().literal("$"),
+            NinjaStr::new().raw("$").raw("$")
+        );
+        assert_eq!(NinjaStr::new(), "");
+        assert_eq!(NinjaStr::from("x").escaped_path(), "x");
+        assert_eq!(NinjaStr::new().literal(" a").literal(" b"), "$ a b");
+        assert_eq!(NinjaStr::new().literal(" a").escaped_path(), "$ a");
+        // line continuations are skipped
+        assert_eq!(NinjaStr::from("a $\n    b$$$\n  c$"), "a b$$c$");
+        assert_ne!(NinjaStr::from("a$$\nb"), "a$$b");
+    }
+
+    #[test]
+    fn test_to_arg() {
+        assert_eq!(Literal("a b").to_path_arg(), "a$ b");
+        assert_eq!(Literal("a b").to_arg(), "a b");
+        assert_eq!(Raw("a b").to_path_arg(), "a b");
+        assert_eq!(VarRef("x").to_path_arg(), "$x");
+        assert_eq!("a b".to_ninja_str(), NinjaStr::from("a b"));
+    }
+}
@@ -0,0 +1,730 @@
+//! Parser for reading existing ninja files
+//!
+//! The parser follows the [lexer rules](https://ninja-build.org/manual.html#ref_lexer) of ninja.
+//! Paths and values are stored as-is (i.e. still escaped), the same way the builder API stores
+//! them, so that the output of [`Display`] can be parsed back into the same statements.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::bytes::stored_byte;
+use crate::stmt::Stmt;
+use crate::util::{RefCounted, SharedVec};
+use crate::{Build, Ninja, Pool, Rule, Variable};
+
+/// Error when parsing a ninja file
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let err = Ninja::parse("rule cc\n  command = $!\n").unwrap_err();
+/// assert_eq!(err.line, 2);
+/// assert_eq!(err.column, 13);
+/// assert_eq!(err.kind, ParseErrorKind::BadEscape);
+/// assert_eq!(err.to_string(), "line 2, column 13: bad $-escape (literal $ must be written as $$)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line number where the error occured, starting from 1
+    pub line: usize,
+    /// The column (in bytes) where the error occured, starting from 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
+
+/// The kind of a [`ParseError`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `$` is followed by something that is not a valid escape
+    BadEscape,
+    /// An indented line is found outside of a `rule`, `build` or `pool` block
+    UnexpectedIndent,
+    /// A tab is used for indentation or as whitespace
+    Tab,
+    /// Something else is found where the given token is expected
+    Expected(&'static str),
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::BadEscape => write!(f, "bad $-escape (literal $ must be written as $$)"),
+            Self::UnexpectedIndent => write!(f, "unexpected indent"),
+            Self::Tab => write!(f, "tabs are not allowed, use spaces"),
+            Self::Expected(what) => write!(f, "expected {}", what),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Parse the input and add the statements to the ninja file
+///
+/// `tags` are the sorted positions of the bytes stored by
+/// [`encode_bytes`](crate::bytes::encode_bytes) in the input. A `$` followed by a reserved
+/// character is only allowed at these positions.
+pub(crate) fn parse_into(ninja: &Ninja, input: &str, tags: &[usize]) -> Result<(), ParseError> {
+    let mut parser = Parser::new(input, RefCounted::clone(&ninja.phony.name));
+    parser.tags = tags;
+    while let Some((_, stmt)) = parser.next_stmt()? {
+        ninja.stmts.add_rc(stmt);
+    }
+    Ok(())
+}
+
+/// Statement-by-statement parser over the input
+pub(crate) struct Parser<'a> {
+    /// The input
+    src: &'a str,
+    /// Current position in the input, in bytes
+    pos: usize,
+    /// Rule names that are declared so far, so the names can be shared with the build edges
+    rules: BTreeMap<&'a str, RefCounted<String>>,
+    /// Sorted positions of the bytes stored by [`encode_bytes`](crate::bytes::encode_bytes)
+    tags: &'a [usize],
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str, phony: RefCounted<String>) -> Self {
+        let mut rules = BTreeMap::new();
+        rules.insert("phony", phony);
+        Self {
+            src,
+            pos: 0,
+            rules,
+            tags: &[],
+        }
+    }
+
+    /// Current position in the input
+    #[inline]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Parse the next statement, skipping blank lines.
+    ///
+    /// Returns the position where the statement starts and the statement,
+    /// or `None` at the end of the input. Top-level comments are returned as [`Stmt::Comment`]
+    pub fn next_stmt(&mut self) -> Result<Option<(usize, Stmt)>, ParseError> {
+        loop {
+            let start = self.pos;
+            let indent = self.skip_spaces();
+            match self.peek() {
+                None => return Ok(None),
+                Some(b'\n') => {
+                    self.pos += 1;
+                }
+                Some(b'\r') if self.peek_at(1) == Some(b'\n') => {
+                    self.pos += 2;
+                }
+                Some(b'#') => {
+                    self.check_comment()?;
+                    let comment = self.read_comment();
+                    return Ok(Some((start, Stmt::Comment(comment.to_string()))));
+                }
+                Some(b'\t') => return Err(self.error(ParseErrorKind::Tab)),
+                Some(_) if indent > 0 => return Err(self.error(ParseErrorKind::UnexpectedIndent)),
+                Some(_) => {
+                    let stmt = self.parse_stmt()?;
+                    return Ok(Some((start, stmt)));
+                }
+            }
+        }
+    }
+
+    /// Parse a statement that starts with an identifier
+    fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let ident = self.read_ident()?;
+        let stmt = match ident {
+            "rule" => {
+                self.skip_ws()?;
+                let name = self.read_ident()?;
+                self.expect_newline()?;
+                let variables = self.parse_block()?;
+                let rc_name = RefCounted::new(name.to_string());
+                self.rules.insert(name, RefCounted::clone(&rc_name));
+                Stmt::Rule(Rule {
+                    name: rc_name,
+                    variables,
+                    auto_escape: false,
+                })
+            }
+            "build" => Stmt::Build(Box::new(self.parse_build()?)),
+            "pool" => {
+                self.skip_ws()?;
+                let name = self.read_ident()?.to_string();
+                self.expect_newline()?;
+                let variables = self.parse_block()?;
+                Stmt::Pool(Pool { name, variables })
+            }
+            "default" => {
+                self.skip_ws()?;
+                let outputs = self.read_paths()?;
+                if outputs.is_empty() {
+                    return Err(self.error(ParseErrorKind::Expected("path")));
+                }
+                self.expect_newline()?;
+                Stmt::Default(outputs)
+            }
+            "include" | "subninja" => {
+                self.skip_ws()?;
+                let path = self.read_path()?;
+                if path.is_empty() {
+                    return Err(self.error(ParseErrorKind::Expected("path")));
+                }
+                let path = path.to_string();
+                self.skip_ws()?;
+                self.expect_newline()?;
+                if ident == "include" {
+                    Stmt::Include(path)
+                } else {
+                    Stmt::Subninja(path)
+                }
+            }
+            name => {
+                let value = self.parse_let_value()?;
+                Stmt::Variable(Variable {
+                    name: name.to_string(),
+                    value: value.into(),
+                })
+            }
+        };
+        Ok(stmt)
+    }
+
+    /// Parse the rest of a build line and its variable block
+    fn parse_build(&mut self) -> Result<Build, ParseError> {
+        self.skip_ws()?;
+        let outputs = self.read_paths()?;
+        let implicit_outputs = if self.eat_pipe("|") {
+            self.skip_ws()?;
+            self.read_paths()?
+        } else {
+            Vec::new()
+        };
+        if outputs.is_empty() && implicit_outputs.is_empty() {
+            return Err(self.error(ParseErrorKind::Expected("path")));
+        }
+        if self.peek() != Some(b':') {
+            return Err(self.error(ParseErrorKind::Expected("':'")));
+        }
+        self.pos += 1;
+        self.skip_ws()?;
+        let rule_name = self.read_ident()?;
+        let rule = match self.rules.get(rule_name) {
+            Some(rule) => RefCounted::clone(rule),
+            None => RefCounted::new(rule_name.to_string()),
+        };
+        self.skip_ws()?;
+        let dependencies = self.read_paths()?;
+        let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+        for (i, pipe) in ["|", "||", "|@"].into_iter().enumerate() {
+            if self.eat_pipe(pipe) {
+                self.skip_ws()?;
+                lists[i] = self.read_paths()?;
+            }
+        }
+        self.expect_newline()?;
+        let [implicit_dependencies, order_only_dependencies, validations] = lists;
+        Ok(Build {
+            rule,
+            outputs: into_shared_vec(outputs),
+            implicit_outputs: into_shared_vec(implicit_outputs),
+            dependencies: into_shared_vec(dependencies),
+            implicit_dependencies: into_shared_vec(implicit_dependencies),
+            order_only_dependencies: into_shared_vec(order_only_dependencies),
+            validations: into_shared_vec(validations),
+            variables: self.parse_block()?,
+            auto_escape: false,
+        })
+    }
+
+    /// Parse an indented block of variables following a `rule`, `build` or `pool` line
+    fn parse_block(&mut self) -> Result<SharedVec<Variable>, ParseError> {
+        let variables = SharedVec::new();
+        loop {
+            // comments don't end the block if an indented line follows them
+            let checkpoint = self.pos;
+            loop {
+                self.skip_spaces();
+                if self.peek() != Some(b'#') {
+                    break;
+                }
+                self.read_comment();
+            }
+            let line_start = self.line_start();
+            let indent = self.pos - line_start;
+            match self.peek() {
+                Some(b'\t') => return Err(self.error(ParseErrorKind::Tab)),
+                None | Some(b'\n' | b'\r') => {
+                    self.pos = checkpoint;
+                    break;
+                }
+                Some(_) if indent == 0 => {
+                    self.pos = checkpoint;
+                    break;
+                }
+                Some(_) => {}
+            }
+            let name = self.read_ident()?;
+            let value = self.parse_let_value()?;
+            variables.add(Variable {
+                name: name.to_string(),
+                value: value.into(),
+            });
+        }
+        Ok(variables)
+    }
+
+    /// Parse the ` = value\n` part of a variable declaration
+    fn parse_let_value(&mut self) -> Result<&'a str, ParseError> {
+        self.skip_ws()?;
+        if self.peek() != Some(b'=') {
+            return Err(self.error(ParseErrorKind::Expected("'='")));
+        }
+        self.pos += 1;
+        self.skip_ws()?;
+        let value = self.read_value()?;
+        self.expect_newline()?;
+        Ok(value)
+    }
+
+    /// Read a list of paths separated by whitespace
+    fn read_paths(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut paths = Vec::new();
+        loop {
+            let path = self.read_path()?;
+            if path.is_empty() {
+                return Ok(paths);
+            }
+            paths.push(path.to_string());
+            self.skip_ws()?;
+        }
+    }
+
+    /// Read a path, which ends at an unescaped space, `:`, `|` or newline
+    fn read_path(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            match c {
+                b' ' | b':' | b'|' | b'\n' | b'\r' => break,
+                b'$' => self.read_escape()?,
+                _ => self.pos += 1,
+            }
+        }
+        Ok(&self.src[start..self.pos])
+    }
+
+    /// Read a variable value, which ends at the newline
+    fn read_value(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            match c {
+                b'\n' => break,
+                b'\r' if self.peek_at(1) == Some(b'\n') => break,
+                b'$' => self.read_escape()?,
+                _ => self.pos += 1,
+            }
+        }
+        Ok(&self.src[start..self.pos])
+    }
+
+    /// Validate and skip over a `$` escape. The position is at the `$`
+    fn read_escape(&mut self) -> Result<(), ParseError> {
+        match self.peek_at(1) {
+            Some(b'$' | b' ' | b':') => self.pos += 2,
+            Some(b'\n') => {
+                self.pos += 2;
+                self.skip_spaces();
+            }
+            Some(b'\r') if self.peek_at(2) == Some(b'\n') => {
+                self.pos += 3;
+                self.skip_spaces();
+            }
+            Some(b'{') => {
+                self.pos += 2;
+                let len = self.count_while(is_ident_char);
+                if len == 0 || self.peek_at(len) != Some(b'}') {
+                    self.pos -= 2;
+                    return Err(self.error(ParseErrorKind::BadEscape));
+                }
+                self.pos += len + 1;
+            }
+            Some(c) if is_simple_var_char(c) => {
+                self.pos += 1;
+                self.pos += self.count_while(is_simple_var_char);
+            }
+            Some(_) if self.is_tag(self.pos) => {
+                let c = self.src[self.pos + 1..].chars().next();
+                match c.filter(|c| stored_byte(*c).is_some()) {
+                    Some(c) => self.pos += 1 + c.len_utf8(),
+                    None => return Err(self.error(ParseErrorKind::BadEscape)),
+                }
+            }
+            _ => return Err(self.error(ParseErrorKind::BadEscape)),
+        }
+        Ok(())
+    }
+
+    /// Read an identifier, which is the name of a variable, rule, pool, or a keyword
+    fn read_ident(&mut self) -> Result<&'a str, ParseError> {
+        let len = self.count_while(is_ident_char);
+        if len == 0 {
+            return Err(self.error(ParseErrorKind::Expected("identifier")));
+        }
+        let ident = &self.src[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(ident)
+    }
+
+    /// Check that a comment line is written back as it was read. The position is at the `#`
+    ///
+    /// A `$` followed by a reserved character in a comment would be written as a byte that is
+    /// not valid UTF-8 (see [`Bytes`](crate::Bytes)), so it is only allowed for those bytes.
+    fn check_comment(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let end = self.src[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(self.src.len());
+        // the same as the `ByteDecoder`, which skips `$$`
+        let mut chars = self.src[start..end].char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c != '$' {
+                continue;
+            }
+            let pos = start + i;
+            let bad = match chars.peek() {
+                Some((_, '$')) => self.is_tag(pos + 1),
+                Some((_, c)) if stored_byte(*c).is_some() => !self.is_tag(pos),
+                _ => continue,
+            };
+            if bad {
+                self.pos = pos;
+                return Err(self.error(ParseErrorKind::BadEscape));
+            }
+            chars.next();
+        }
+        Ok(())
+    }
+
+    /// Check if a byte stored by [`encode_bytes`](crate::bytes::encode_bytes) starts at the position
+    #[inline]
+    fn is_tag(&self, pos: usize) -> bool {
+        self.tags.binary_search(&pos).is_ok()
+    }
+
+    /// Read a comment line and return the comment text.
+    /// The position is at the `#` and is moved to the start of the next line
+    fn read_comment(&mut self) -> &'a str {
+        let start = self.pos + 1;
+        let end = self.src[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(self.src.len());
+        self.pos = (end + 1).min(self.src.len());
+        let comment = self.src[start..end]
+            .strip_suffix('\r')
+            .unwrap_or(&self.src[start..end]);
+        comment.strip_prefix(' ').unwrap_or(comment)
+    }
+
+    /// Consume one of the `|`, `||` or `|@` separators if it is next
+    fn eat_pipe(&mut self, pipe: &str) -> bool {
+        let rest = &self.src.as_bytes()[self.pos..];
+        let matched = match pipe {
+            "|" => rest.starts_with(b"|") && !rest.starts_with(b"||") && !rest.starts_with(b"|@"),
+            _ => rest.starts_with(pipe.as_bytes()),
+        };
+        if matched {
+            self.pos += pipe.len();
+        }
+        matched
+    }
+
+    /// Expect the end of a line (or the end of input) and consume it
+    fn expect_newline(&mut self) -> Result<(), ParseError> {
+        self.skip_ws()?;
+        match self.peek() {
+            None => {}
+            Some(b'\n') => self.pos += 1,
+            Some(b'\r') if self.peek_at(1) == Some(b'\n') => self.pos += 2,
+            Some(b'\t') => return Err(self.error(ParseErrorKind::Tab)),
+            Some(_) => return Err(self.error(ParseErrorKind::Expected("newline"))),
+        }
+        Ok(())
+    }
+
+    /// Skip spaces and `$\n` line continuations
+    fn skip_ws(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_spaces();
+            let rest = &self.src.as_bytes()[self.pos..];
+            if rest.starts_with(b"$\n") {
+                self.pos += 2;
+            } else if rest.starts_with(b"$\r\n") {
+                self.pos += 3;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skip spaces and return how many are skipped
+    fn skip_spaces(&mut self) -> usize {
+        let len = self.count_while(|c| c == b' ');
+        self.pos += len;
+        len
+    }
+
+    /// Count the number of bytes from the current position that satisfy the predicate
+    fn count_while(&self, pred: impl Fn(u8) -> bool) -> usize {
+        self.src.as_bytes()[self.pos..]
+            .iter()
+            .take_while(|c| pred(**c))
+            .count()
+    }
+
+    /// Position of the start of the current line
+    fn line_start(&self) -> usize {
+        self.src[..self.pos].rfind('\n').map(|i| i + 1).unwrap_or(0)
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.peek_at(0)
+    }
+
+    #[inline]
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.src.as_bytes().get(self.pos + offset).copied()
+    }
+
+    /// Create an error at the current position
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        let before = &self.src[..self.pos];
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: self.pos - self.line_start() + 1,
+            kind,
+        }
+    }
+}
+
+fn into_shared_vec(list: Vec<String>) -> SharedVec<String> {
+    let v = SharedVec::new();
+    v.extend(list);
+    v
+}
+
+/// Characters allowed in identifiers (`[a-zA-Z0-9_.-]`)
+#[inline]
+pub(crate) fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'-')
+}
+
+/// Characters allowed in a `$var` reference without braces (`[a-zA-Z0-9_-]`)
+#[inline]
+fn is_simple_var_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    fn parse(input: &str) -> Vec<RefCounted<Stmt>> {
+        Ninja::parse(input).unwrap().stmts.inner().clone()
+    }
+
+    fn parse_err(input: &str) -> (usize, usize, ParseErrorKind) {
+        let err = Ninja::parse(input).unwrap_err();
+        (err.line, err.column, err.kind)
+    }
+
+    fn vars(list: &SharedVec<Variable>) -> Vec<(String, String)> {
+        list.inner()
+            .iter()
+            .map(|v| (v.name.clone(), v.value.to_string()))
+            .collect()
+    }
+
+    fn strings(list: &SharedVec<String>) -> Vec<String> {
+        list.inner().clone()
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(parse("").is_empty());
+        assert!(parse("\n  \n\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_build_sections() {
+        let stmts = parse("build a $:b | c: r d$ e | f || g |@ h\n  x = y\n");
+        let Stmt::Build(build) = stmts[0].as_ref() else {
+            panic!("expected build");
+        };
+        assert_eq!(build.rule.as_str(), "r");
+        assert_eq!(strings(&build.outputs), vec!["a", "$:b"]);
+        assert_eq!(strings(&build.implicit_outputs), vec!["c"]);
+        assert_eq!(strings(&build.dependencies), vec!["d$ e"]);
+        assert_eq!(strings(&build.implicit_dependencies), vec!["f"]);
+        assert_eq!(strings(&build.order_only_dependencies), vec!["g"]);
+        assert_eq!(strings(&build.validations), vec!["h"]);
+        assert_eq!(vars(&build.variables), vec![("x".into(), "y".into())]);
+    }
+
+    #[test]
+    fn test_build_no_space() {
+        let stmts = parse("build a|b:r c||d|@e");
+        let Stmt::Build(build) = stmts[0].as_ref() else {
+            panic!("expected build");
+        };
+        assert_eq!(strings(&build.outputs), vec!["a"]);
+        assert_eq!(strings(&build.implicit_outputs), vec!["b"]);
+        assert_eq!(strings(&build.dependencies), vec!["c"]);
+        assert_eq!(strings(&build.order_only_dependencies), vec!["d"]);
+        assert_eq!(strings(&build.validations), vec!["e"]);
+    }
+
+    #[test]
+    fn test_build_shares_rule_name() {
+        let ninja = Ninja::parse("rule r\n  command = c\nbuild a: r\nbuild b: phony\n").unwrap();
+        let stmts = ninja.stmts.inner();
+        let (Stmt::Rule(rule), Stmt::Build(build)) = (stmts[0].as_ref(), stmts[1].as_ref()) else {
+            panic!("expected rule and build");
+        };
+        assert!(RefCounted::ptr_eq(&rule.name, &build.rule));
+        let Stmt::Build(build) = stmts[2].as_ref() else {
+            panic!("expected build");
+        };
+        assert!(RefCounted::ptr_eq(&ninja.phony.name, &build.rule));
+    }
+
+    #[test]
+    fn test_continuation() {
+        let stmts = parse("build a: r b $\n    c$\n  d\nx = 1 $\n  2\n");
+        let Stmt::Build(build) = stmts[0].as_ref() else {
+            panic!("expected build");
+        };
+        assert_eq!(strings(&build.dependencies), vec!["b", "c$\n  d"]);
+        let Stmt::Variable(var) = stmts[1].as_ref() else {
+            panic!("expected variable");
+        };
+        assert_eq!(var.value, "1 $\n  2");
+    }
+
+    #[test]
+    fn test_value_verbatim() {
+        let stmts = parse("x =   a: b | $c ${d.e} $$ $ $: \n");
+        let Stmt::Variable(var) = stmts[0].as_ref() else {
+            panic!("expected variable");
+        };
+        assert_eq!(var.name, "x");
+        assert_eq!(var.value, "a: b | $c ${d.e} $$ $ $: ");
+    }
+
+    #[test]
+    fn test_comments() {
+        let stmts = parse("#a\n# b\nrule r\n  # c\n# d\n  command = x\n# e\n\n  # f\n");
+        let comments: Vec<_> = stmts
+            .iter()
+            .filter_map(|s| match s.as_ref() {
+                Stmt::Comment(c) => Some(c.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(comments, vec!["a", "b", "e", "f"]);
+        let Stmt::Rule(rule) = stmts[2].as_ref() else {
+            panic!("expected rule");
+        };
+        assert_eq!(vars(&rule.variables), vec![("command".into(), "x".into())]);
+    }
+
+    #[test]
+    fn test_block_ends_at_blank_line() {
+        assert_eq!(
+            parse_err("pool p\n  depth = 1\n\n  x = y\n"),
+            (4, 3, ParseErrorKind::UnexpectedIndent)
+        );
+    }
+
+    #[test]
+    fn test_crlf() {
+        let stmts = parse("rule r\r\n  command = x\r\ndefault a b\r\n");
+        let Stmt::Rule(rule) = stmts[0].as_ref() else {
+            panic!("expected rule");
+        };
+        assert_eq!(vars(&rule.variables), vec![("command".into(), "x".into())]);
+        assert!(matches!(stmts[1].as_ref(), Stmt::Default(d) if d == &["a", "b"]));
+    }
+
+    #[test]
+    fn test_include_subninja() {
+        let stmts = parse("include a$ b.ninja\nsubninja $dir/c.ninja\n");
+        assert!(matches!(stmts[0].as_ref(), Stmt::Include(p) if p == "a$ b.ninja"));
+        assert!(matches!(stmts[1].as_ref(), Stmt::Subninja(p) if p == "$dir/c.ninja"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_err("  x = y"),
+            (1, 3, ParseErrorKind::UnexpectedIndent)
+        );
+        assert_eq!(parse_err("x = $"), (1, 5, ParseErrorKind::BadEscape));
+        assert_eq!(parse_err("x = ${a"), (1, 5, ParseErrorKind::BadEscape));
+        assert_eq!(parse_err("x y"), (1, 3, ParseErrorKind::Expected("'='")));
+        assert_eq!(
+            parse_err("build a r"),
+            (1, 10, ParseErrorKind::Expected("':'"))
+        );
+        assert_eq!(
+            parse_err("build a: b: c"),
+            (1, 11, ParseErrorKind::Expected("newline"))
+        );
+        assert_eq!(
+            parse_err("include\n"),
+            (1, 8, ParseErrorKind::Expected("path"))
+        );
+        assert_eq!(
+            parse_err("build : phony\n"),
+            (1, 7, ParseErrorKind::Expected("path"))
+        );
+        assert_eq!(
+            parse_err("default\n"),
+            (1, 8, ParseErrorKind::Expected("path"))
+        );
+        assert_eq!(
+            parse_err("rule\n"),
+            (1, 5, ParseErrorKind::Expected("identifier"))
+        );
+        assert_eq!(
+            parse_err("rule r\n\tcommand = x"),
+            (2, 1, ParseErrorKind::Tab)
+        );
+        assert_eq!(
+            parse_err("rule r\n  command = x\n  $y = z"),
+            (3, 3, ParseErrorKind::Expected("identifier"))
+        );
+    }
+}
@@ -5,7 +5,7 @@ use core::fmt::{Display, Formatter, Result};
 use core::ops::Deref;
 
 use crate::stmt::{Stmt, StmtRef};
-use crate::util::{AddOnlyVec, Indented};
+use crate::util::{Indented, SharedVec};
 use crate::{Ninja, ToArg, Variable, Variables};
 
 /// A pool, as defined by the `pool` keyword
@@ -32,14 +32,14 @@ use crate::{Ninja, ToArg, Variable, Variables};
 ///   pool = expensive
 /// "###);
 /// ```
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Pool {
     /// Name of the pool
     pub name: String,
     /// The list of variables, as an indented block
     ///
     /// Currently the only useful variable is `depth`
-    pub variables: AddOnlyVec<Variable>,
+    pub variables: SharedVec<Variable>,
 }
 
 /// Reference to a pool statement
@@ -63,12 +63,22 @@ impl AsRef<Pool> for PoolRef {
     }
 }
 
+impl PoolRef {
+    /// Remove the pool from the ninja file, and return if it was in the ninja file
+    ///
+    /// See [`StmtRef::remove`]
+    #[inline]
+    pub fn remove(&self) -> bool {
+        self.0.remove()
+    }
+}
+
 impl Pool {
     /// Create a pool with a given name and depth
     pub fn new(name: impl ToArg, depth: usize) -> Self {
         let x = Self {
             name: name.to_arg(),
-            variables: AddOnlyVec::new(),
+            variables: SharedVec::new(),
         };
         x.variable("depth", depth)
     }
@@ -84,12 +94,29 @@ impl Variables for Pool {
     fn add_variable_internal(&self, v: Variable) {
         self.variables.add(v);
     }
+    fn set_variable_internal(&self, v: Variable) {
+        let name = v.name.clone();
+        self.variables.set(|x| x.name == name, v);
+    }
+
+    fn remove_variable_internal(&self, name: &str) {
+        self.variables.retain(|x| x.name != name);
+    }
 }
 
 impl Variables for PoolRef {
     fn add_variable_internal(&self, v: Variable) {
         self.deref().add_variable_internal(v);
     }
+    #[inline]
+    fn set_variable_internal(&self, v: Variable) {
+        self.deref().set_variable_internal(v);
+    }
+
+    #[inline]
+    fn remove_variable_internal(&self, name: &str) {
+        self.deref().remove_variable_internal(name);
+    }
 }
 
 impl Display for Pool {
@@ -0,0 +1,539 @@
+//! Options for rendering the ninja file
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Result, Write};
+
+use crate::bytes::LossyWriter;
+use crate::eval::evaluate;
+use crate::stmt::Stmt;
+use crate::util::{Indented, RefCounted};
+use crate::{Ninja, Variable};
+
+/// When to put blank lines between statements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlankLines {
+    /// No blank lines
+    Never,
+    /// A blank line between groups of statements of the same kind, and before each rule
+    #[default]
+    BetweenGroups,
+    /// A blank line between every statement
+    BetweenStatements,
+}
+
+/// Options for rendering the ninja file with [`Ninja::render`]
+///
+/// The default options give the same output as [`Display`](core::fmt::Display).
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let ninja = Ninja::new();
+/// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+/// cc.build(["foo.o"]).with(["foo.c"]);
+/// cc.build(["bar.o"]).with(["bar.c"]);
+///
+/// let options = RenderOptions::new()
+///     .indent(4)
+///     .blank_lines(BlankLines::BetweenStatements)
+///     .header("generated by configure.py, do not edit")
+///     .leading_newline(false);
+///
+/// assert_eq!(ninja.render(&options), r###"# generated by configure.py, do not edit
+/// rule cc
+///     command = gcc -c $in -o $out
+///
+/// build foo.o: cc foo.c
+///
+/// build bar.o: cc bar.c
+/// "###);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The number of spaces to indent variables in rules, builds and pools
+    ///
+    /// Variables that are not indented would be top-level variables, so `0` is taken as `1`.
+    pub indent: usize,
+    /// When to put blank lines between statements
+    pub blank_lines: BlankLines,
+    /// Text written as comments at the top of the file
+    pub header: Option<String>,
+    /// If there is a blank line before the first statement
+    pub leading_newline: bool,
+    /// The width to wrap long lines at. If `None`, the width set with
+    /// [`Ninja::wrap_width`] is used, if any
+    pub wrap_width: Option<usize>,
+    /// If the statements are sorted for reproducible output. See [`canonical`](Self::canonical)
+    pub canonical: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            blank_lines: BlankLines::default(),
+            header: None,
+            leading_newline: true,
+            wrap_width: None,
+            canonical: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Create the default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of spaces to indent variables in rules, builds and pools
+    ///
+    /// `0` is taken as `1`, see [`indent`](Self::indent).
+    #[inline]
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Set when to put blank lines between statements
+    #[inline]
+    pub fn blank_lines(mut self, blank_lines: BlankLines) -> Self {
+        self.blank_lines = blank_lines;
+        self
+    }
+
+    /// Set the text written as comments at the top of the file, one comment per line
+    #[inline]
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = Some(header.into());
+        self
+    }
+
+    /// Set if there is a blank line before the first statement
+    #[inline]
+    pub fn leading_newline(mut self, leading_newline: bool) -> Self {
+        self.leading_newline = leading_newline;
+        self
+    }
+
+    /// Set the width to wrap long lines at, instead of the one set with [`Ninja::wrap_width`]
+    #[inline]
+    pub fn wrap_width(mut self, width: usize) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    /// Sort the statements, so the output doesn't depend on the order they were added in
+    ///
+    /// This is useful when build edges are added from multiple threads.
+    /// Top-level variables, `include` and `subninja` statements are kept in place,
+    /// since what comes after them depends on them. Between them, pools and rules are
+    /// sorted by name, followed by build edges sorted by their first output, and then
+    /// `default` statements. Comments stay with the statement after them.
+    ///
+    /// Variables in rules, builds and pools are sorted by name, unless a name is declared
+    /// more than once, or (in builds and pools, where variables are expanded immediately)
+    /// a variable references another one in the same block.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.variable("cflags", "-O2");
+    /// let cc = ninja.rule("cc", "gcc $cflags -c $in -o $out");
+    /// cc.build(["foo.o"]).with(["foo.c"]);
+    /// ninja.comment("the main file");
+    /// cc.build(["bar.o"])
+    ///     .with(["bar.c"])
+    ///     .variable("cflags", "-O0")
+    ///     .variable("anchor", "x");
+    /// let ld = ninja.rule("ld", "gcc $in -o $out");
+    /// ld.build(["app"]).with(["foo.o", "bar.o"]);
+    ///
+    /// assert_eq!(ninja.render(&RenderOptions::new().canonical()), r###"
+    /// cflags = -O2
+    ///
+    /// rule cc
+    ///   command = gcc $cflags -c $in -o $out
+    ///
+    /// rule ld
+    ///   command = gcc $in -o $out
+    ///
+    /// build app: ld foo.o bar.o
+    ///
+    /// ## the main file
+    ///
+    /// build bar.o: cc bar.c
+    ///   anchor = x
+    ///   cflags = -O0
+    /// build foo.o: cc foo.c
+    /// "###);
+    /// ```
+    #[inline]
+    pub fn canonical(mut self) -> Self {
+        self.canonical = true;
+        self
+    }
+}
+
+impl Ninja {
+    /// Render the ninja file with the given options
+    ///
+    /// See [`RenderOptions`] for an example.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let mut output = String::new();
+        // writing to a string doesn't fail
+        let _ = self.render_to(&mut output, options);
+        output
+    }
+
+    /// Render the ninja file with the given options to a [`core::fmt::Write`],
+    /// statement by statement
+    ///
+    /// Bytes that are not valid UTF-8 (see [`Bytes`](crate::Bytes)) are written as `U+FFFD`.
+    pub fn render_to(&self, writer: &mut impl Write, options: &RenderOptions) -> Result {
+        let mut writer = LossyWriter::new(writer);
+        self.render_stored(&mut writer, options)?;
+        writer.finish()
+    }
+
+    /// Render the ninja file as it is stored, with the bytes from [`Bytes`](crate::Bytes)
+    /// still encoded
+    pub(crate) fn render_stored(&self, writer: &mut impl Write, options: &RenderOptions) -> Result {
+        if let Some(header) = &options.header {
+            for line in header.lines() {
+                writeln!(writer, "# {}", line)?;
+            }
+        }
+        // variables that are not indented would end the block
+        let indent = options.indent.max(1);
+        let wrap_width = options.wrap_width.or(self.wrap_width);
+        let list = &self.all_stmts();
+        let order = match options.canonical {
+            true => canonical_order(list),
+            false => (0..list.len()).collect(),
+        };
+        let mut last = None;
+        for index in order {
+            let stmt = list[index].as_ref();
+            if stmt.is_empty() {
+                continue;
+            }
+            let blank = match last {
+                None => options.leading_newline,
+                Some(last) => match options.blank_lines {
+                    BlankLines::Never => false,
+                    BlankLines::BetweenGroups => {
+                        matches!(stmt, Stmt::Rule(_)) || stmt.ordinal() != last
+                    }
+                    BlankLines::BetweenStatements => true,
+                },
+            };
+            if blank {
+                writer.write_char('\n')?;
+            }
+            last = Some(stmt.ordinal());
+
+            let sorted = match options.canonical {
+                true => sorted_block(stmt),
+                false => None,
+            };
+            if indent == 2 && wrap_width.is_none() {
+                match sorted {
+                    Some(text) => writer.write_str(&text)?,
+                    None => write!(writer, "{}", stmt)?,
+                }
+                continue;
+            }
+            let text = sorted.unwrap_or_else(|| stmt.to_string());
+            for (i, line) in text.lines().enumerate() {
+                // variables in blocks are indented with 2 spaces
+                let line = match line.strip_prefix("  ") {
+                    Some(rest) if i > 0 && indent != 2 => {
+                        Cow::Owned(format!("{:indent$}{}", "", rest))
+                    }
+                    _ => Cow::Borrowed(line),
+                };
+                match wrap_width {
+                    Some(width) => write_wrapped(writer, &line, width)?,
+                    None => writeln!(writer, "{}", line)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the order to write the statements in for canonical output
+fn canonical_order(list: &[RefCounted<Stmt>]) -> Vec<usize> {
+    /// How statements are sorted between top-level variables, includes and subninjas
+    fn sort_key(stmt: &Stmt) -> (u8, String) {
+        match stmt {
+            Stmt::Pool(pool) => (0, pool.name.to_string()),
+            Stmt::Rule(rule) => (1, rule.name.to_string()),
+            Stmt::Build(build) => (
+                2,
+                build.outputs.inner().first().cloned().unwrap_or_default(),
+            ),
+            _ => (3, String::new()),
+        }
+    }
+
+    let mut order = Vec::with_capacity(list.len());
+    // statements since the last variable, include or subninja, with the comments before them
+    let mut segment: Vec<(usize, usize)> = Vec::new();
+    let mut start = 0;
+    let flush = |segment: &mut Vec<(usize, usize)>, order: &mut Vec<usize>| {
+        let mut keyed: Vec<_> = segment
+            .drain(..)
+            .map(|(start, end)| (sort_key(&list[end]), start, end))
+            .collect();
+        keyed.sort_by(|(a, _, a_end), (b, _, b_end)| {
+            // ties are broken by the whole statement, to not depend on the insertion order
+            a.cmp(b)
+                .then_with(|| list[*a_end].to_string().cmp(&list[*b_end].to_string()))
+        });
+        for (_, start, end) in keyed {
+            order.extend(start..=end);
+        }
+    };
+    for (i, stmt) in list.iter().enumerate() {
+        match stmt.as_ref() {
+            Stmt::Comment(_) => continue,
+            Stmt::Variable(_) | Stmt::Include(_) | Stmt::Subninja(_) => {
+                flush(&mut segment, &mut order);
+                order.extend(start..=i);
+            }
+            _ => segment.push((start, i)),
+        }
+        start = i + 1;
+    }
+    flush(&mut segment, &mut order);
+    // comments at the end
+    order.extend(start..list.len());
+    order
+}
+
+/// Render a rule, build or pool with its variables sorted by name, if that is safe
+fn sorted_block(stmt: &Stmt) -> Option<String> {
+    let (variables, immediate) = match stmt {
+        Stmt::Rule(rule) => (&rule.variables, false),
+        Stmt::Build(build) => (&build.variables, true),
+        Stmt::Pool(pool) => (&pool.variables, true),
+        _ => return None,
+    };
+    let variables = variables.inner();
+    let mut sorted: Vec<&Variable> = variables.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    if sorted.windows(2).any(|pair| pair[0].name == pair[1].name) {
+        return None;
+    }
+    if immediate {
+        // the value of a variable that is expanded immediately can depend on the order
+        let mut references = false;
+        for variable in sorted.iter() {
+            let _ = evaluate(&variable.value.escaped(), |name| {
+                references |= name != variable.name && sorted.iter().any(|v| v.name == name);
+                Ok(String::new())
+            });
+        }
+        if references {
+            return None;
+        }
+    }
+    let mut output = match stmt {
+        Stmt::Rule(rule) => format!("rule {}", rule.name),
+        Stmt::Pool(pool) => format!("pool {}", pool.name),
+        Stmt::Build(build) => {
+            let mut output = String::new();
+            let _ = build.write_header(&mut output);
+            output
+        }
+        _ => return None,
+    };
+    output.push('\n');
+    for variable in sorted {
+        let _ = writeln!(output, "{}", Indented(variable));
+    }
+    Some(output)
+}
+
+/// Write a line, breaking it with `$` continuations at unescaped spaces to fit in `width`
+///
+/// This is the same algorithm as `ninja_syntax.py`
+fn write_wrapped(writer: &mut impl Write, line: &str, width: usize) -> Result {
+    let mut text = line.trim_start_matches(' ');
+    let indent = line.len() - text.len();
+    if text.starts_with('#') {
+        return writeln!(writer, "{}", line);
+    }
+    let mut leading = indent;
+    while leading + text.chars().count() > width {
+        // the space to break at, leaving room for " $"
+        let available = width.saturating_sub(leading + 2);
+        let end = text
+            .char_indices()
+            .nth(available)
+            .map_or(text.len(), |(i, _)| i);
+        // ninja skips the indentation after a line continuation, so only the last space
+        // of a run can be broken at
+        let can_break = |i: &usize| !is_escaped(text, *i) && !text[i + 1..].starts_with(' ');
+        let space = text[..end]
+            .rmatch_indices(' ')
+            .map(|(i, _)| i)
+            .find(can_break)
+            .or_else(|| {
+                // no such space, use the first unescaped space after it
+                text[end..]
+                    .match_indices(' ')
+                    .map(|(i, _)| end + i)
+                    .find(can_break)
+            });
+        let Some(space) = space else {
+            break;
+        };
+        writeln!(writer, "{:leading$}{} $", "", &text[..space])?;
+        text = &text[space + 1..];
+        leading = indent + 4;
+    }
+    writeln!(writer, "{:leading$}{}", "", text)
+}
+
+/// Check if the character at `index` is escaped by an odd number of `$` before it
+fn is_escaped(text: &str, index: usize) -> bool {
+    let dollars = text[..index]
+        .bytes()
+        .rev()
+        .take_while(|b| *b == b'$')
+        .count();
+    dollars % 2 == 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wrapped(line: &str, width: usize) -> String {
+        let mut output = String::new();
+        write_wrapped(&mut output, line, width).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrapped("a b c", 5), "a b c\n");
+        assert_eq!(wrapped("aaa bbb ccc", 8), "aaa $\n    bbb $\n    ccc\n");
+        // escaped spaces are not broken
+        assert_eq!(wrapped("a b$ c", 5), "a $\n    b$ c\n");
+        // spaces after the break would be skipped by ninja
+        assert_eq!(wrapped("aa  bb", 5), "aa  $\n    bb\n");
+        assert_eq!(wrapped("a$$ bc", 5), "a$$ $\n    bc\n");
+        // no space that fits, use the first one after
+        assert_eq!(wrapped("aaaaaa bb", 5), "aaaaaa $\n    bb\n");
+        assert_eq!(wrapped("aaaaaaaaa", 5), "aaaaaaaaa\n");
+        // indentation is kept
+        assert_eq!(wrapped("  x = aa bb", 9), "  x = $\n      aa $\n      bb\n");
+        assert_eq!(wrapped("# aaa bbb ccc", 5), "# aaa bbb ccc\n");
+    }
+
+    #[test]
+    fn test_wrap_width_of_ninja() {
+        let ninja = Ninja::new().wrap_width(8);
+        ninja.defaults(["aaa", "bbb"]);
+        let expected = "\ndefault $\n    aaa $\n    bbb\n";
+        assert_eq!(ninja.to_string(), expected);
+        assert_eq!(ninja.render(&RenderOptions::new()), expected);
+        assert_eq!(
+            ninja.render(&RenderOptions::new().wrap_width(80)),
+            "\ndefault aaa bbb\n"
+        );
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let ninja = Ninja::new();
+        ninja.variable("a", "1");
+        ninja.variable("b", "2");
+        ninja.rule("r", "cmd").build(["x"]);
+        assert_eq!(ninja.render(&RenderOptions::new()), ninja.to_string());
+        let options = RenderOptions::new()
+            .blank_lines(BlankLines::Never)
+            .leading_newline(false)
+            .indent(4);
+        assert_eq!(
+            ninja.render(&options),
+            "a = 1\nb = 2\nrule r\n    command = cmd\nbuild x: r\n"
+        );
+        let options = RenderOptions::new()
+            .blank_lines(BlankLines::BetweenStatements)
+            .header("line 1\nline 2");
+        assert_eq!(
+            ninja.render(&options),
+            "# line 1\n# line 2\n\na = 1\n\nb = 2\n\nrule r\n  command = cmd\n\nbuild x: r\n"
+        );
+        assert_eq!(Ninja::new().render(&options), "# line 1\n# line 2\n");
+    }
+
+    #[test]
+    fn test_indent_zero() {
+        use crate::Variables;
+
+        let ninja = Ninja::new();
+        ninja.rule("r", "cmd").build(["x"]).variable("y", "1");
+        let output = ninja.render(&RenderOptions::new().indent(0));
+        assert_eq!(output, "\nrule r\n command = cmd\n\nbuild x: r\n y = 1\n");
+        assert_eq!(Ninja::parse(&output).unwrap(), ninja);
+    }
+
+    #[test]
+    fn test_canonical() {
+        use crate::{RuleVariables, Variables};
+
+        let ninja = Ninja::new();
+        ninja.variable("v", "1");
+        let rule = ninja.rule("r", "cmd").depfile("$out.d").description("r");
+        rule.build(["c"]).variable("b", "$a").variable("a", "1");
+        rule.build(["b"]).variable("y", "1").variable("y", "2");
+        ninja.defaults(["a"]);
+        rule.build(["a"]).variable("y", "$y 2").variable("x", "1");
+        ninja.pool("p", 1);
+        ninja.include("other.ninja");
+        rule.build(["0"]);
+        ninja.comment("end");
+
+        let options = RenderOptions::new()
+            .canonical()
+            .blank_lines(BlankLines::Never);
+        assert_eq!(
+            ninja.render(&options),
+            "
+v = 1
+pool p
+  depth = 1
+rule r
+  command = cmd
+  depfile = $out.d
+  description = r
+build a: r
+  x = 1
+  y = $y 2
+build b: r
+  y = 1
+  y = 2
+build c: r
+  b = $a
+  a = 1
+default a
+include other.ninja
+build 0: r
+# end
+"
+        );
+    }
+}
@@ -5,9 +5,10 @@ use alloc::string::String;
 use core::fmt::{Display, Formatter, Result};
 use core::ops::Deref;
 
+use crate::arg::try_to_args;
 use crate::stmt::{Stmt, StmtRef};
-use crate::util::{AddOnlyVec, Indented, RefCounted};
-use crate::{Build, BuildRef, Ninja, Pool, ToArg, Variable, Variables};
+use crate::util::{Indented, RefCounted, SharedVec};
+use crate::{Build, BuildRef, Error, Ninja, Pool, ToArg, TryToArg, Variable, Variables};
 
 /// A rule, as defined by the `rule` keyword
 ///
@@ -121,7 +122,12 @@ pub struct Rule {
     /// The list of variables, as an indented block
     ///
     /// See <https://ninja-build.org/manual.html#ref_rule>
-    pub variables: AddOnlyVec<Variable>,
+    pub variables: SharedVec<Variable>,
+
+    /// If paths of build edges using this rule are escaped automatically
+    ///
+    /// See [`Ninja::auto_escape`](crate::Ninja::auto_escape)
+    pub auto_escape: bool,
 }
 
 /// Trait for implementing variables for `rule` and `build`
@@ -348,6 +354,13 @@ pub trait RuleVariables: Variables {
     }
 }
 
+impl PartialEq for Rule {
+    /// Compare the names and variables of the rules, but not [`auto_escape`](Self::auto_escape)
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.variables == other.variables
+    }
+}
+
 /// Reference to a rule statement that can be used to create build edges
 /// using this rule
 #[derive(Debug, Clone)]
@@ -371,15 +384,39 @@ impl AsRef<Rule> for RuleRef {
 }
 
 impl RuleRef {
+    /// Remove the rule from the ninja file, and return if it was in the ninja file
+    ///
+    /// Build edges using the rule are not removed. See [`StmtRef::remove`]
+    #[inline]
+    pub fn remove(&self) -> bool {
+        self.0.remove()
+    }
+
     /// Create a build edge using this rule and the explicit outputs, then add it to
     /// the ninja file provided.
     ///
+    /// Ninja rejects a build edge without outputs, so it is skipped when rendering
+    /// unless implicit outputs are added with
+    /// [`output_implicit`](crate::BuildVariables::output_implicit).
+    ///
     /// # Example
     /// See [`Rule`]
     pub fn build(&self, outputs: impl IntoIterator<Item = impl ToArg>) -> BuildRef {
         let build = Build::new(self.deref(), outputs);
         BuildRef(self.0.add(Stmt::Build(Box::new(build))))
     }
+
+    /// Create a build edge using this rule and the explicit outputs, then add it to
+    /// the ninja file provided, or return an error if any output can't be converted.
+    ///
+    /// Nothing is added if there is an error.
+    /// See [`Ninja::try_rule`](crate::Ninja::try_rule) for an example.
+    pub fn try_build(
+        &self,
+        outputs: impl IntoIterator<Item = impl TryToArg>,
+    ) -> core::result::Result<BuildRef, Error> {
+        Ok(self.build(try_to_args(outputs)?))
+    }
 }
 
 impl Rule {
@@ -387,13 +424,57 @@ impl Rule {
     pub fn new(name: impl ToArg, command: impl ToArg) -> Self {
         let s = Self {
             name: RefCounted::new(name.to_arg()),
-            variables: AddOnlyVec::new(),
+            variables: SharedVec::new(),
+            auto_escape: false,
         };
         s.variable("command", command)
     }
 
+    /// Create a new rule that runs a [`Command`](std::process::Command), quoted for the shell
+    /// of the platform, or return an error if the command can't be converted
+    ///
+    /// See [`ShellCommand::try_from_command`](crate::ShellCommand::try_from_command) for how
+    /// the command is converted, and the errors.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::process::Command;
+    /// use ninja_writer::*;
+    ///
+    /// let mut command = Command::new("protoc");
+    /// command.arg("--version").env("PATH", "/opt/protobuf/bin");
+    ///
+    /// let ninja = Ninja::new();
+    /// Rule::try_from_command("protoc_version", &command).unwrap().add_to(&ninja);
+    ///
+    /// # #[cfg(not(windows))]
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule protoc_version
+    ///   command = env PATH=/opt/protobuf/bin protoc --version
+    /// "###);
+    /// ```
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn try_from_command(
+        name: impl ToArg,
+        command: &std::process::Command,
+    ) -> core::result::Result<Self, Error> {
+        Ok(Self::new(name, command.try_to_arg()?))
+    }
+
+    /// Escape the paths of build edges using this rule automatically
+    ///
+    /// This is enabled when the rule is added to a ninja file that has auto-escaping enabled.
+    /// See [`Ninja::auto_escape`](crate::Ninja::auto_escape)
+    #[inline]
+    pub fn auto_escape(mut self) -> Self {
+        self.auto_escape = true;
+        self
+    }
+
     /// Add the rule to a ninja file and return a [`RuleRef`] for further configuration
-    pub fn add_to(self, ninja: &Ninja) -> RuleRef {
+    pub fn add_to(mut self, ninja: &Ninja) -> RuleRef {
+        self.auto_escape |= ninja.auto_escape;
         RuleRef(ninja.add_stmt(Stmt::Rule(self)))
     }
 }
@@ -403,6 +484,14 @@ impl Variables for Rule {
     fn add_variable_internal(&self, v: Variable) {
         self.variables.add(v);
     }
+    fn set_variable_internal(&self, v: Variable) {
+        let name = v.name.clone();
+        self.variables.set(|x| x.name == name, v);
+    }
+
+    fn remove_variable_internal(&self, name: &str) {
+        self.variables.retain(|x| x.name != name);
+    }
 }
 
 impl RuleVariables for Rule {}
@@ -411,6 +500,15 @@ impl Variables for RuleRef {
     fn add_variable_internal(&self, v: Variable) {
         self.deref().add_variable_internal(v)
     }
+    #[inline]
+    fn set_variable_internal(&self, v: Variable) {
+        self.deref().set_variable_internal(v);
+    }
+
+    #[inline]
+    fn remove_variable_internal(&self, name: &str) {
+        self.deref().remove_variable_internal(name);
+    }
 }
 impl RuleVariables for RuleRef {}
 
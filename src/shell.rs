@@ -0,0 +1,562 @@
+//! Quoting arguments of commands for the shell
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use core::fmt::{Display, Formatter, Result};
+
+use crate::bytes::encode_bytes;
+use crate::{Segment, ToArg, escape};
+
+/// The shell that runs the commands, which decides how arguments are quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shell {
+    /// POSIX `sh`, which ninja uses on non-Windows platforms.
+    ///
+    /// Arguments are quoted with single quotes.
+    #[default]
+    Posix,
+    /// Windows `CreateProcess`, which ninja uses on Windows.
+    ///
+    /// Arguments are quoted with double quotes, following the rules of `CommandLineToArgvW`.
+    /// Arguments with `cmd` metacharacters are quoted as well, but this is not enough for
+    /// `cmd /c`: it doesn't understand `\"` inside quotes, and it expands `%name%`.
+    Windows,
+}
+
+impl Shell {
+    /// Get the shell of the platform this is compiled for
+    #[inline]
+    pub fn host() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Posix
+        }
+    }
+
+    /// Quote an argument if it contains characters that are special to the shell.
+    ///
+    /// No ninja escaping is done.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// assert_eq!(Shell::Posix.quote("foo.c"), "foo.c");
+    /// assert_eq!(Shell::Posix.quote("it's here"), r#"'it'\''s here'"#);
+    /// assert_eq!(Shell::Windows.quote(r#"C:\my dir\"#), r#""C:\my dir\\""#);
+    /// assert_eq!(Shell::Windows.quote(r#"say "hi""#), r#""say \"hi\"""#);
+    /// ```
+    pub fn quote<'a>(&self, arg: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Posix => {
+                let is_safe = |c: char| {
+                    c.is_ascii_alphanumeric()
+                        || matches!(c, '_' | '+' | '-' | '.' | '/' | '=' | ':' | ',' | '@' | '%')
+                };
+                if !arg.is_empty() && arg.chars().all(is_safe) {
+                    return Cow::Borrowed(arg);
+                }
+                Cow::Owned(posix_quote(arg))
+            }
+            Self::Windows => {
+                let is_special = |c: char| {
+                    c.is_whitespace() || matches!(c, '"' | '&' | '|' | '<' | '>' | '^' | '(' | ')')
+                };
+                if !arg.is_empty() && !arg.contains(is_special) {
+                    return Cow::Borrowed(arg);
+                }
+                Cow::Owned(win32_quote(arg))
+            }
+        }
+    }
+}
+
+/// Quote an argument with single quotes for POSIX shell
+pub(crate) fn posix_quote(arg: &str) -> String {
+    let mut output = String::with_capacity(arg.len() + 2);
+    output.push('\'');
+    for c in arg.chars() {
+        if c == '\'' {
+            output.push_str("'\\''");
+        } else {
+            output.push(c);
+        }
+    }
+    output.push('\'');
+    output
+}
+
+/// Quote an argument with double quotes for `CommandLineToArgvW`
+pub(crate) fn win32_quote(arg: &str) -> String {
+    let mut output = String::with_capacity(arg.len() + 2);
+    output.push('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                output.extend(core::iter::repeat_n('\\', backslashes + 1));
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        output.push(c);
+    }
+    output.extend(core::iter::repeat_n('\\', backslashes));
+    output.push('"');
+    output
+}
+
+/// A command for a rule, with each argument quoted for the shell and escaped for ninja
+///
+/// Variable references like `$in`, `$out` or `${cflags}` in the arguments are kept as is,
+/// so they are expanded by ninja. The rest of the text is quoted for the [`Shell`],
+/// then escaped with [`escape`]. A `$` that doesn't start a variable reference is taken
+/// literally, and so is all of the text in a [`Literal`](crate::Literal).
+/// Use [`raw_arg`](Self::raw_arg) to add text that is neither quoted nor escaped.
+///
+/// # Example
+/// ```rust
+/// use ninja_writer::*;
+///
+/// let command = ShellCommand::new(Shell::Posix, "gcc")
+///     .args(["-c", "$in", "-o", "$out"])
+///     .arg("-DGREETING=hello world")
+///     .arg("-I$builddir/my include")
+///     .arg("-DPRICE=5$")
+///     .arg(Literal("$HOME"))
+///     .raw_arg("$cflags");
+/// assert_eq!(
+///     command.as_str(),
+///     "gcc -c $in -o $out '-DGREETING=hello world' -I$builddir'/my include' '-DPRICE=5$$' '$$HOME' $cflags"
+/// );
+///
+/// let command = ShellCommand::new(Shell::Windows, r"C:\Program Files\LLVM\bin\clang.exe")
+///     .args(["-c", "$in", "-o", "$out"]);
+/// assert_eq!(
+///     command.as_str(),
+///     r#""C:\Program Files\LLVM\bin\clang.exe" -c $in -o $out"#
+/// );
+///
+/// let ninja = Ninja::new();
+/// ninja.rule("cc", command);
+/// assert_eq!(ninja.to_string(), r###"
+/// rule cc
+///   command = "C:\Program Files\LLVM\bin\clang.exe" -c $in -o $out
+/// "###);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellCommand {
+    /// The shell the command is quoted for
+    shell: Shell,
+    /// The quoted and escaped command
+    command: String,
+}
+
+impl ShellCommand {
+    /// Create a command that runs the program
+    pub fn new(shell: Shell, program: impl ToArg) -> Self {
+        Self {
+            shell,
+            command: String::new(),
+        }
+        .arg(program)
+    }
+
+    /// Add an argument, which is quoted and escaped
+    ///
+    /// Variable references are kept, except in [`Literal`](crate::Literal) text,
+    /// where everything is quoted and escaped.
+    ///
+    /// # Panic
+    /// This will panic if the argument contains a newline. Commands are a single line in
+    /// ninja, and an escaped newline (`$` at the end of a line) is a line continuation,
+    /// so the newline would be dropped from the argument.
+    pub fn arg(mut self, arg: impl ToArg) -> Self {
+        self.push_separator();
+        let start = self.command.len();
+        for segment in arg.to_ninja_str().segments() {
+            match segment {
+                Segment::Raw(text) => {
+                    let mut rest = text.as_str();
+                    while !rest.is_empty() {
+                        let (text, var) = split_var(rest);
+                        self.push_literal(text);
+                        self.command.push_str(var);
+                        rest = &rest[text.len() + var.len()..];
+                    }
+                }
+                Segment::Literal(text) => self.push_literal(text),
+                Segment::VarRef(name) => {
+                    self.command.push_str("${");
+                    self.command.push_str(name);
+                    self.command.push('}');
+                }
+                Segment::Bytes(bytes) => {
+                    // bytes that are not valid UTF-8 are not special to the shell, but
+                    // they are quoted to be safe, and they are not escaped for ninja
+                    let quote = match self.shell {
+                        Shell::Posix => '\'',
+                        Shell::Windows => '"',
+                    };
+                    self.command.push(quote);
+                    self.command.push_str(&encode_bytes(bytes));
+                    self.command.push(quote);
+                }
+            }
+        }
+        if self.command.len() == start {
+            self.command.push_str(&self.shell.quote(""));
+        }
+        self
+    }
+
+    /// Add arguments, which are quoted and escaped
+    pub fn args(self, args: impl IntoIterator<Item = impl ToArg>) -> Self {
+        args.into_iter().fold(self, Self::arg)
+    }
+
+    /// Add text that is already quoted and escaped, like `$cflags` that expands
+    /// to multiple arguments
+    pub fn raw_arg(mut self, arg: impl ToArg) -> Self {
+        self.push_separator();
+        self.command.push_str(&arg.to_arg());
+        self
+    }
+
+    /// Get the shell the command is quoted for
+    #[inline]
+    pub fn shell(&self) -> Shell {
+        self.shell
+    }
+
+    /// Get the command, quoted and escaped
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.command
+    }
+
+    /// Quote and escape literal text
+    fn push_literal(&mut self, text: &str) {
+        assert!(
+            !text.contains('\n'),
+            "command argument contains a newline: {text:?}"
+        );
+        if !text.is_empty() {
+            self.command.push_str(&escape(&self.shell.quote(text)));
+        }
+    }
+
+    fn push_separator(&mut self) {
+        if !self.command.is_empty() {
+            self.command.push(' ');
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ShellCommand {
+    /// Convert a [`Command`](std::process::Command) to a command for a rule.
+    ///
+    /// The program and arguments are taken literally. If the command has a current directory
+    /// or environment variables, they become prefixes:
+    /// - [`Shell::Posix`]: `cd <dir> && env -u <removed> <K>=<V> <program> <args>`
+    /// - [`Shell::Windows`]: `cmd /c cd /d <dir> && set "<K>=<V>" && <program> <args>`
+    ///
+    /// # Panic
+    /// This will panic if the command can't be converted, see
+    /// [`try_from_command`](Self::try_from_command).
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::process::Command;
+    /// use ninja_writer::*;
+    ///
+    /// let mut command = Command::new("cargo");
+    /// command.args(["build", "--target-dir", "my target"])
+    ///     .env("RUSTFLAGS", "-C opt-level=3")
+    ///     .env_remove("CARGO_HOME")
+    ///     .current_dir("/src/my crate");
+    ///
+    /// assert_eq!(
+    ///     ShellCommand::from_command(Shell::Posix, &command).as_str(),
+    ///     "cd '/src/my crate' && env -u CARGO_HOME 'RUSTFLAGS=-C opt-level=3' cargo build --target-dir 'my target'"
+    /// );
+    /// assert_eq!(
+    ///     ShellCommand::from_command(Shell::Windows, &command).as_str(),
+    ///     r#"cmd /c cd /d "/src/my crate" && set "CARGO_HOME=" && set "RUSTFLAGS=-C opt-level=3" && cargo build --target-dir "my target""#
+    /// );
+    /// ```
+    #[inline]
+    pub fn from_command(shell: Shell, command: &std::process::Command) -> Self {
+        Self::try_from_command(shell, command).unwrap()
+    }
+
+    /// Convert a [`Command`](std::process::Command) to a command for a rule, or return an
+    /// error if it can't be converted
+    ///
+    /// See [`from_command`](Self::from_command) for how the command is converted.
+    /// The errors are:
+    /// - [`Error::InvalidUtf8Path`](crate::Error::InvalidUtf8Path) if any part of the command is not valid UTF-8.
+    /// - [`Error::Unquotable`](crate::Error::Unquotable) if any part contains a newline (see [`arg`](Self::arg)).
+    /// - [`Error::Unquotable`](crate::Error::Unquotable) for [`Shell::Windows`], if the command
+    ///   runs with `cmd /c` and any part contains `"` or `%`. `cmd` can't escape them in
+    ///   quoted strings: a `"` ends the quoted string, even after `\`, and `%` expands variables.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::process::Command;
+    /// use ninja_writer::*;
+    ///
+    /// let mut command = Command::new("echo");
+    /// command.env("PERCENT", "100%");
+    ///
+    /// assert_eq!(
+    ///     ShellCommand::try_from_command(Shell::Posix, &command).unwrap().as_str(),
+    ///     "env PERCENT=100% echo"
+    /// );
+    /// assert_eq!(
+    ///     ShellCommand::try_from_command(Shell::Windows, &command),
+    ///     Err(Error::Unquotable("100%".to_string()))
+    /// );
+    /// ```
+    pub fn try_from_command(
+        shell: Shell,
+        command: &std::process::Command,
+    ) -> core::result::Result<Self, crate::Error> {
+        use crate::{Error, Literal, TryToArg};
+
+        let no_newline = |text: String| match text.contains('\n') {
+            true => Err(Error::Unquotable(text)),
+            false => Ok(text),
+        };
+        let program = no_newline(command.get_program().try_to_arg()?)?;
+        let args = command
+            .get_args()
+            .map(|arg| no_newline(arg.try_to_arg()?))
+            .collect::<core::result::Result<std::vec::Vec<_>, _>>()?;
+        let dir = match command.get_current_dir() {
+            Some(dir) => Some(no_newline(dir.try_to_arg()?)?),
+            None => None,
+        };
+        let mut envs = std::vec::Vec::new();
+        for (name, value) in command.get_envs() {
+            let name = no_newline(name.try_to_arg()?)?;
+            let value = match value {
+                Some(value) => Some(no_newline(value.try_to_arg()?)?),
+                None => None,
+            };
+            envs.push((name, value));
+        }
+        // removed variables first, since `env` takes options before assignments
+        envs.sort_by_key(|(_, value)| value.is_some());
+
+        let mut output = Self {
+            shell,
+            command: String::new(),
+        };
+        match shell {
+            Shell::Posix => {
+                if let Some(dir) = dir {
+                    output = output.raw_arg("cd").arg(Literal(dir)).raw_arg("&&");
+                }
+                if !envs.is_empty() {
+                    output = output.raw_arg("env");
+                }
+                for (name, value) in envs {
+                    output = match value {
+                        Some(value) => output.arg(Literal(std::format!("{}={}", name, value))),
+                        None => output.raw_arg("-u").arg(Literal(name)),
+                    };
+                }
+            }
+            Shell::Windows if dir.is_some() || !envs.is_empty() => {
+                // `cmd` doesn't understand `\"` in quoted arguments, and expands `%name%`
+                let texts = envs
+                    .iter()
+                    .flat_map(|(name, value)| [Some(name), value.as_ref()])
+                    .chain([dir.as_ref(), Some(&program)])
+                    .chain(args.iter().map(Some))
+                    .flatten();
+                for text in texts {
+                    if text.contains(['"', '%']) {
+                        return Err(Error::Unquotable(text.clone()));
+                    }
+                }
+                output = output.raw_arg("cmd /c");
+                if let Some(dir) = dir {
+                    output = output.raw_arg("cd /d").arg(Literal(dir)).raw_arg("&&");
+                }
+                for (name, value) in envs {
+                    let value = value.unwrap_or_default();
+                    let assignment = std::format!("{}={}", name, value);
+                    output = output.raw_arg(std::format!("set \"{}\" &&", escape(&assignment)));
+                }
+            }
+            Shell::Windows => {}
+        }
+        Ok(output
+            .arg(Literal(program))
+            .args(args.into_iter().map(Literal)))
+    }
+}
+
+/// Convert a command with [`ShellCommand::try_from_command`], for the shell of the platform
+#[cfg(feature = "std")]
+impl crate::TryToArg for &std::process::Command {
+    #[inline]
+    fn try_to_arg(self) -> core::result::Result<String, crate::Error> {
+        ShellCommand::try_from_command(Shell::host(), self).map(ToArg::to_arg)
+    }
+}
+
+/// Split the text into the literal text before the first variable reference,
+/// and the variable reference
+fn split_var(text: &str) -> (&str, &str) {
+    let is_var_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    let mut start = 0;
+    while let Some(i) = text[start..].find('$') {
+        let i = start + i;
+        let after = &text[i + 1..];
+        if after.starts_with('{') {
+            if let Some(end) = after.find('}') {
+                return (&text[..i], &text[i..i + end + 2]);
+            }
+        } else {
+            let len = after.find(|c| !is_var_char(c)).unwrap_or(after.len());
+            if len > 0 {
+                return (&text[..i], &text[i..i + len + 1]);
+            }
+        }
+        start = i + 1;
+    }
+    (text, "")
+}
+
+impl Display for ShellCommand {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(&self.command)
+    }
+}
+
+impl ToArg for ShellCommand {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.command
+    }
+}
+
+impl ToArg for &ShellCommand {
+    #[inline]
+    fn to_arg(self) -> String {
+        self.command.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_var() {
+        assert_eq!(split_var("a$b c"), ("a", "$b"));
+        assert_eq!(split_var("${a.b}c"), ("", "${a.b}"));
+        assert_eq!(split_var("$ $5$"), ("$ ", "$5"));
+        assert_eq!(split_var("${a"), ("${a", ""));
+        assert_eq!(split_var("$"), ("$", ""));
+    }
+
+    #[test]
+    fn test_empty_and_special() {
+        let command = ShellCommand::new(Shell::Posix, "echo")
+            .arg("")
+            .arg("a;b")
+            .arg("$$");
+        assert_eq!(command.as_str(), "echo '' 'a;b' '$$$$'");
+        let command = ShellCommand::new(Shell::Windows, "echo")
+            .arg("")
+            .arg("a&b")
+            .arg("${out}.d")
+            .arg("C:\\a b\\$in");
+        assert_eq!(
+            command.as_str(),
+            "echo \"\" \"a&b\" ${out}.d \"C:\\a b\\\\\"$in"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "newline")]
+    fn test_newline() {
+        // ninja would read `$` and the newline as a line continuation
+        let _ = ShellCommand::new(Shell::Posix, "echo").arg("x\ny");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_command_literal() {
+        let mut command = std::process::Command::new("echo");
+        command.arg("$HOME").arg("").env("A", "$B");
+        assert_eq!(
+            ShellCommand::from_command(Shell::Posix, &command).as_str(),
+            "env 'A=$$B' echo '$$HOME' ''"
+        );
+        assert_eq!(
+            ShellCommand::from_command(Shell::Windows, &command).as_str(),
+            "cmd /c set \"A=$$B\" && echo $$HOME \"\""
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_command_windows() {
+        use crate::{Error, TryToArg};
+
+        // without `cmd`, `CreateProcess` understands `\"`
+        let mut command = std::process::Command::new(r"C:\Program Files\tool.exe");
+        command.arg(r#"say "hi" & bye"#);
+        assert_eq!(
+            ShellCommand::from_command(Shell::Windows, &command).as_str(),
+            r#""C:\Program Files\tool.exe" "say \"hi\" & bye""#
+        );
+
+        let mut command = std::process::Command::new(r"C:\Program Files\tool.exe");
+        command
+            .arg("say hi & bye")
+            .env("A", "x & y")
+            .current_dir(r"C:\my dir\");
+        assert_eq!(
+            ShellCommand::from_command(Shell::Windows, &command).as_str(),
+            r#"cmd /c cd /d "C:\my dir\\" && set "A=x & y" && "C:\Program Files\tool.exe" "say hi & bye""#
+        );
+
+        // `cmd` can't escape these in quoted strings
+        for text in [r#"a"b"#, r#"a\"&calc"#, "%PATH%"] {
+            let mut env = std::process::Command::new("echo");
+            env.env("A", text);
+            let mut arg = std::process::Command::new("echo");
+            arg.arg(text).env("A", "x");
+            let mut dir = std::process::Command::new("echo");
+            dir.current_dir(text);
+            let mut program = std::process::Command::new(text);
+            program.current_dir("dir");
+            for command in [env, arg, dir, program] {
+                assert_eq!(
+                    ShellCommand::try_from_command(Shell::Windows, &command),
+                    Err(Error::Unquotable(text.into()))
+                );
+                assert!(ShellCommand::try_from_command(Shell::Posix, &command).is_ok());
+            }
+        }
+        command.env("A", "x").arg("a\nb");
+        assert_eq!(
+            ShellCommand::try_from_command(Shell::Posix, &command),
+            Err(Error::Unquotable("a\nb".into()))
+        );
+        assert_eq!(
+            (&command).try_to_arg(),
+            Err(Error::Unquotable("a\nb".into()))
+        );
+    }
+}
@@ -3,13 +3,15 @@
 use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result};
 use core::ops::Deref;
 
-use crate::util::{AddOnlyVec, RefCounted};
+use crate::ninja::Root;
+use crate::util::{RefCounted, SharedVec};
 use crate::{Build, Pool, Rule, Variable};
 
 /// A top-level ninja statement
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Stmt {
     /// A Comment (`# <comment>`)
     Comment(String),
@@ -67,6 +69,43 @@ impl Stmt {
     pub fn is_same_type(&self, other: &Self) -> bool {
         self.ordinal() == other.ordinal()
     }
+
+    /// Check if this is a `build` without outputs or a `default` without targets,
+    /// which ninja rejects. These statements are skipped when rendering, and builds
+    /// without outputs are reported by [`Ninja::validate`](crate::Ninja::validate).
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Build(build) => {
+                build.outputs.inner().is_empty() && build.implicit_outputs.inner().is_empty()
+            }
+            Self::Default(outputs) => outputs.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Rule(rule) => rule.fmt(f),
+            Self::Build(build) => build.fmt(f),
+            Self::Pool(pool) => pool.fmt(f),
+            Self::Comment(comment) => writeln!(f, "# {}", comment),
+            Self::Variable(variable) => {
+                variable.fmt(f)?;
+                writeln!(f)
+            }
+            Self::Default(outputs) => {
+                write!(f, "default")?;
+                for output in outputs {
+                    write!(f, " {}", output)?;
+                }
+                writeln!(f)
+            }
+            Self::Subninja(path) => writeln!(f, "subninja {}", path),
+            Self::Include(path) => writeln!(f, "include {}", path),
+        }
+    }
 }
 
 /// A reference to a statement in a list that can be used to get the statement,
@@ -74,9 +113,11 @@ impl Stmt {
 #[derive(Debug)]
 pub struct StmtRef {
     /// The list this statement is in
-    pub(crate) list: RefCounted<AddOnlyVec<RefCounted<Stmt>>>,
+    pub(crate) list: RefCounted<SharedVec<RefCounted<Stmt>>>,
     /// The statement
     pub(crate) stmt: RefCounted<Stmt>,
+    /// The ninja file at the top of the sections the list is in
+    pub(crate) root: Root,
 }
 impl StmtRef {
     /// Add a new statment to the list this statement is in
@@ -84,14 +125,44 @@ impl StmtRef {
         StmtRef {
             stmt: self.list.add_rc(stmt),
             list: RefCounted::clone(&self.list),
+            root: self.root.clone(),
         }
     }
+
+    /// Remove this statement from the list it is in, and return if it was in the list
+    ///
+    /// The reference can still be used to read the statement, and to add new statements
+    /// to the list.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// let foo = cc.build(["foo.o"]).with(["foo.c"]);
+    /// cc.build(["bar.o"]).with(["bar.c"]);
+    /// assert!(foo.remove());
+    /// assert!(!foo.remove());
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = gcc -c $in -o $out
+    ///
+    /// build bar.o: cc bar.c
+    /// "###);
+    /// ```
+    pub fn remove(&self) -> bool {
+        self.list
+            .retain(|stmt| !RefCounted::ptr_eq(stmt, &self.stmt))
+    }
 }
 impl Clone for StmtRef {
     fn clone(&self) -> Self {
         Self {
             list: RefCounted::clone(&self.list),
             stmt: RefCounted::clone(&self.stmt),
+            root: self.root.clone(),
         }
     }
 }
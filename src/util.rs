@@ -3,10 +3,10 @@ use alloc::borrow::{Cow, ToOwned};
 use alloc::string::String;
 use alloc::vec::Vec;
 #[cfg(not(feature = "thread-safe"))]
-use core::cell::{Ref, RefCell};
+use core::cell::{Ref, RefCell, RefMut};
 use core::fmt::{Display, Formatter, Result};
 #[cfg(feature = "thread-safe")]
-use std::sync::{RwLock, RwLockReadGuard};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 /// Helper type to write indented things
 pub struct Indented<TDisplay>(pub TDisplay)
@@ -204,20 +204,35 @@ pub type RefCounted<T> = alloc::sync::Arc<T>;
 #[cfg(not(feature = "thread-safe"))]
 pub type RefCounted<T> = alloc::rc::Rc<T>;
 
-/// A list that can only be added to, with interior mutability
+#[cfg(feature = "thread-safe")]
+pub type WeakRef<T> = alloc::sync::Weak<T>;
+#[cfg(not(feature = "thread-safe"))]
+pub type WeakRef<T> = alloc::rc::Weak<T>;
+
+/// A list with interior mutability, shared by the references to a statement
+///
+/// Elements are mostly added, but can also be replaced and removed.
 #[derive(Debug)]
-pub struct AddOnlyVec<T> {
+pub struct SharedVec<T> {
     #[cfg(feature = "thread-safe")]
     inner: RwLock<Vec<T>>,
     #[cfg(not(feature = "thread-safe"))]
     inner: RefCell<Vec<T>>,
 }
+/// The old name of [`SharedVec`], from when elements could only be added
+#[deprecated(note = "renamed to `SharedVec`")]
+pub type AddOnlyVec<T> = SharedVec<T>;
+
 #[cfg(feature = "thread-safe")]
 pub type VecInnerGuard<'a, T> = RwLockReadGuard<'a, Vec<T>>;
 #[cfg(not(feature = "thread-safe"))]
 pub type VecInnerGuard<'a, T> = Ref<'a, Vec<T>>;
+#[cfg(feature = "thread-safe")]
+type VecInnerGuardMut<'a, T> = RwLockWriteGuard<'a, Vec<T>>;
+#[cfg(not(feature = "thread-safe"))]
+type VecInnerGuardMut<'a, T> = RefMut<'a, Vec<T>>;
 
-impl<T> AddOnlyVec<T> {
+impl<T> SharedVec<T> {
     pub fn new() -> Self {
         #[cfg(feature = "thread-safe")]
         {
@@ -251,6 +266,57 @@ impl<T> AddOnlyVec<T> {
         self.inner.borrow_mut().extend(iter);
     }
 
+    /// Get the first element matching the predicate, or add a new one if there is none
+    ///
+    /// The list is locked while searching, so the element is only added once.
+    pub fn find_or_add(&self, predicate: impl Fn(&T) -> bool, make: impl FnOnce() -> T) -> T
+    where
+        T: Clone,
+    {
+        #[cfg(feature = "thread-safe")]
+        let mut inner = self.inner.write().unwrap();
+        #[cfg(not(feature = "thread-safe"))]
+        let mut inner = self.inner.borrow_mut();
+        if let Some(element) = inner.iter().find(|element| predicate(element)) {
+            return element.clone();
+        }
+        let element = make();
+        inner.push(element.clone());
+        element
+    }
+
+    /// Keep only the elements matching the predicate, and return if any element was removed
+    pub fn retain(&self, mut keep: impl FnMut(&T) -> bool) -> bool {
+        let mut inner = self.inner_mut();
+        let len = inner.len();
+        inner.retain(|element| keep(element));
+        inner.len() != len
+    }
+
+    /// Replace the first element matching the predicate and remove the other matches,
+    /// or add the element if there is no match
+    pub fn set(&self, matches: impl Fn(&T) -> bool, element: T) {
+        let mut inner = self.inner_mut();
+        let Some(first) = inner.iter().position(&matches) else {
+            inner.push(element);
+            return;
+        };
+        inner[first] = element;
+        let mut i = 0;
+        inner.retain(|element| {
+            i += 1;
+            i <= first + 1 || !matches(element)
+        });
+    }
+
+    /// Mutably borrow the inner vector for write access
+    fn inner_mut(&self) -> VecInnerGuardMut<'_, T> {
+        #[cfg(feature = "thread-safe")]
+        return self.inner.write().unwrap();
+        #[cfg(not(feature = "thread-safe"))]
+        self.inner.borrow_mut()
+    }
+
     /// Immutably borrow the inner vector for read access
     pub fn inner(&self) -> VecInnerGuard<'_, T> {
         #[cfg(feature = "thread-safe")]
@@ -260,13 +326,21 @@ impl<T> AddOnlyVec<T> {
     }
 }
 
-impl<T> Default for AddOnlyVec<T> {
+impl<T: PartialEq> PartialEq for SharedVec<T> {
+    /// Compare the elements of the lists
+    fn eq(&self, other: &Self) -> bool {
+        // avoid locking the same list twice
+        core::ptr::eq(self, other) || *self.inner() == *other.inner()
+    }
+}
+
+impl<T> Default for SharedVec<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<T> AddOnlyVec<RefCounted<T>> {
+impl<T> SharedVec<RefCounted<T>> {
     /// Add an element to the list wrapped with ref-counted smart pointer
     pub fn add_rc(&self, element: T) -> RefCounted<T> {
         let rc = RefCounted::new(element);
@@ -274,3 +348,21 @@ impl<T> AddOnlyVec<RefCounted<T>> {
         rc
     }
 }
+
+#[cfg(test)]
+mod test_shared_vec {
+    use super::*;
+
+    #[test]
+    fn test_set_retain() {
+        let list = SharedVec::new();
+        list.extend([1, 2, 3, 2, 4]);
+        list.set(|x| *x == 2, 5);
+        assert_eq!(*list.inner(), [1, 5, 3, 4]);
+        list.set(|x| *x == 6, 6);
+        assert_eq!(*list.inner(), [1, 5, 3, 4, 6]);
+        assert!(list.retain(|x| x % 2 == 0));
+        assert_eq!(*list.inner(), [4, 6]);
+        assert!(!list.retain(|_| true));
+    }
+}
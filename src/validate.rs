@@ -0,0 +1,259 @@
+//! Validation of rule and pool references
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+use crate::eval::evaluate_list;
+use crate::stmt::Stmt;
+use crate::util::RefCounted;
+use crate::{Evaluation, Ninja};
+
+/// An error found by [`Ninja::validate`]
+///
+/// The statement indices count the statements of the ninja file followed by the ones in its
+/// [sections](Ninja::section), in the rendered order. Without sections, they are indices
+/// in [`Ninja::stmts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A build edge uses a rule that is not declared
+    UndefinedRule {
+        /// Name of the rule
+        rule: String,
+        /// Index of the build statement
+        stmt: usize,
+    },
+    /// A build edge uses a rule that is declared after it
+    RuleNotYetDeclared {
+        /// Name of the rule
+        rule: String,
+        /// Index of the build statement
+        stmt: usize,
+        /// Index of the rule statement
+        declared: usize,
+    },
+    /// A build edge uses a pool that is not declared
+    UndefinedPool {
+        /// Name of the pool
+        pool: String,
+        /// Index of the build statement
+        stmt: usize,
+    },
+    /// A build edge uses a pool that is declared after it
+    PoolNotYetDeclared {
+        /// Name of the pool
+        pool: String,
+        /// Index of the build statement
+        stmt: usize,
+        /// Index of the pool statement
+        declared: usize,
+    },
+    /// A build edge has no outputs. Ninja rejects it, so it is not rendered
+    NoOutputs {
+        /// Index of the build statement
+        stmt: usize,
+    },
+}
+
+impl ValidationError {
+    /// Get the index of the build statement with the error
+    pub fn stmt(&self) -> usize {
+        match self {
+            Self::UndefinedRule { stmt, .. }
+            | Self::RuleNotYetDeclared { stmt, .. }
+            | Self::UndefinedPool { stmt, .. }
+            | Self::PoolNotYetDeclared { stmt, .. }
+            | Self::NoOutputs { stmt } => *stmt,
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "statement {}: ", self.stmt())?;
+        self.fmt_message(f)
+    }
+}
+
+impl ValidationError {
+    /// Format the error without the statement index
+    pub(crate) fn fmt_message(&self, f: &mut impl Write) -> FmtResult {
+        match self {
+            Self::UndefinedRule { rule, .. } => write!(f, "unknown build rule '{}'", rule),
+            Self::RuleNotYetDeclared { rule, declared, .. } => write!(
+                f,
+                "rule '{}' is used before it is declared at statement {}",
+                rule, declared
+            ),
+            Self::UndefinedPool { pool, .. } => write!(f, "unknown pool name '{}'", pool),
+            Self::PoolNotYetDeclared { pool, declared, .. } => write!(
+                f,
+                "pool '{}' is used before it is declared at statement {}",
+                pool, declared
+            ),
+            Self::NoOutputs { .. } => write!(f, "build edge has no outputs"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {}
+
+impl Ninja {
+    /// Check that the rule and pool of every build edge are declared before the edge,
+    /// and that every build edge has an output, which ninja requires to load the file.
+    ///
+    /// Build edges without outputs are not rendered, so this is where they are reported.
+    /// The built-in `phony` rule and `console` pool are always declared.
+    /// The `pool` variable is evaluated for each edge, so both `pool` on the build edge and
+    /// on the rule are checked. `include` and `subninja` statements are not followed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = Rule::new("cc", "gcc -c $in -o $out");
+    /// ninja.rule("link", "gcc $in -o $out").variable("pool", "link_pool");
+    /// // the rule is never added to the ninja file
+    /// ninja.stmts.add_rc(Stmt::Build(Box::new(Build::new(&cc, ["foo.o"]))));
+    /// ninja.phony(["all"]).variable("pool", "console");
+    /// ninja.pool("link_pool", 1);
+    ///
+    /// assert_eq!(ninja.validate(), Err(vec![
+    ///     ValidationError::UndefinedRule { rule: "cc".to_string(), stmt: 1 },
+    /// ]));
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let stmts = self.all_stmts();
+        validate_stmts(&stmts, &evaluate_list(&stmts))
+    }
+}
+
+/// Validate the statements, given their evaluation
+pub(crate) fn validate_stmts(
+    stmts: &[RefCounted<Stmt>],
+    evaluation: &Evaluation,
+) -> Result<(), Vec<ValidationError>> {
+    let mut rules = BTreeMap::new();
+    let mut pools = BTreeMap::new();
+    for (i, stmt) in stmts.iter().enumerate() {
+        match stmt.as_ref() {
+            Stmt::Rule(rule) => {
+                rules.entry(rule.name.as_str()).or_insert(i);
+            }
+            Stmt::Pool(pool) => {
+                pools.entry(pool.name.as_str()).or_insert(i);
+            }
+            _ => {}
+        }
+    }
+    let mut errors = Vec::new();
+    for edge in evaluation.edges() {
+        let Some(build) = edge.build() else {
+            continue;
+        };
+        if edge.stmt.is_empty() {
+            errors.push(ValidationError::NoOutputs { stmt: edge.index });
+        }
+        let rule = build.rule.as_str();
+        if rule != "phony" {
+            match rules.get(rule) {
+                Some(&declared) if declared < edge.index => {}
+                Some(&declared) => errors.push(ValidationError::RuleNotYetDeclared {
+                    rule: rule.into(),
+                    stmt: edge.index,
+                    declared,
+                }),
+                None => errors.push(ValidationError::UndefinedRule {
+                    rule: rule.into(),
+                    stmt: edge.index,
+                }),
+            }
+        }
+        // cycles in variables are not a problem of the pool
+        let pool = edge.variable("pool").unwrap_or_default();
+        if pool.is_empty() || pool == "console" {
+            continue;
+        }
+        match pools.get(pool.as_str()) {
+            Some(&declared) if declared < edge.index => {}
+            Some(&declared) => errors.push(ValidationError::PoolNotYetDeclared {
+                pool,
+                stmt: edge.index,
+                declared,
+            }),
+            None => errors.push(ValidationError::UndefinedPool {
+                pool,
+                stmt: edge.index,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BuildVariables, RuleVariables};
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_valid() {
+        let ninja = Ninja::new();
+        let pool = ninja.pool("heavy", 1);
+        let cc = ninja.rule("cc", "gcc -c $in -o $out").pool(&pool);
+        cc.build(["foo.o"]).with(["foo.c"]);
+        cc.build(["bar.o"]).with(["bar.c"]).pool_console();
+        ninja.phony(["all"]).with(["foo.o", "bar.o"]);
+        assert_eq!(ninja.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_order() {
+        let ninja = Ninja::parse(
+            "p = late\nbuild a: cc b\n  pool = $p\nbuild c: undefined d\n  pool = missing\nrule cc\n  command = cc\npool late\n  depth = 1\n",
+        )
+        .unwrap();
+        let errors: Vec<_> = ninja
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "statement 1: rule 'cc' is used before it is declared at statement 3",
+                "statement 1: pool 'late' is used before it is declared at statement 4",
+                "statement 2: unknown build rule 'undefined'",
+                "statement 2: unknown pool name 'missing'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_outputs() {
+        let ninja = Ninja::new();
+        let cc = ninja.rule("cc", "gcc -c $in -o $out");
+        cc.build(["foo.o"]).with(["foo.c"]);
+        cc.build([] as [&str; 0]).with(["bar.c"]);
+        cc.build([] as [&str; 0]).output_implicit(["baz.o"]);
+        assert_eq!(
+            ninja.validate(),
+            Err(vec![ValidationError::NoOutputs { stmt: 2 }])
+        );
+        assert_eq!(
+            ninja.validate().unwrap_err()[0].to_string(),
+            "statement 2: build edge has no outputs"
+        );
+        // the edge is not rendered
+        assert!(!ninja.to_string().contains("bar.c"));
+    }
+}
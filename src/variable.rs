@@ -3,27 +3,30 @@
 use alloc::string::String;
 use core::fmt::{Display, Formatter, Result};
 
-use crate::ToArg;
+use crate::{NinjaStr, ToArg};
 
 /// A variable declaration (`name = value`)
 ///
 /// See <https://ninja-build.org/manual.html#_variables>
 ///
 /// # Escaping
-/// Escaping must be done when constructing the variable. No escaping is done when serializing.
+/// Strings are taken as already escaped. Use [`Literal`](crate::Literal) for text that should
+/// be escaped when serializing, or [`NinjaStr`] to mix both.
 /// ```rust
-/// use ninja_writer::Variable;
+/// use ninja_writer::*;
 ///
 /// let var = Variable::new("foo", "I have a $ in me");
-///
 /// assert_eq!(var.to_string(), "foo = I have a $ in me");
+///
+/// let var = Variable::new("foo", Literal("I have a $ in me"));
+/// assert_eq!(var.to_string(), "foo = I have a $$ in me");
 /// ```
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Variable {
     /// The name of the variable
     pub name: String,
     /// The value of the variable
-    pub value: String,
+    pub value: NinjaStr,
 }
 
 impl Variable {
@@ -31,7 +34,7 @@ impl Variable {
     pub fn new(name: impl ToArg, value: impl ToArg) -> Self {
         Self {
             name: name.to_arg(),
-            value: value.to_arg(),
+            value: value.to_ninja_str(),
         }
     }
 }
@@ -49,9 +52,115 @@ pub trait Variables: Sized {
     /// This is an internal method to add a variable to the current scope.
     fn add_variable_internal(&self, v: Variable);
 
+    /// Set a variable
+    ///
+    /// This is an internal method to replace the variables with the same name
+    /// in the current scope, or add the variable if there are none.
+    ///
+    /// By default, the variables with the same name are removed with
+    /// [`remove_variable_internal`](Self::remove_variable_internal), then the variable is
+    /// added at the end. Override this to keep the position of the variable.
+    fn set_variable_internal(&self, v: Variable) {
+        self.remove_variable_internal(&v.name);
+        self.add_variable_internal(v);
+    }
+
+    /// Remove variables
+    ///
+    /// This is an internal method to remove the variables with the name from the current scope.
+    fn remove_variable_internal(&self, name: &str);
+
     /// Add a variable to the current scope
     fn variable(self, name: impl ToArg, value: impl ToArg) -> Self {
         self.add_variable_internal(Variable::new(name, value));
         self
     }
+
+    /// Set a variable in the current scope, replacing the variable with the same name
+    /// instead of adding another one
+    ///
+    /// The variable keeps its position if it already exists.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out").description("CC $out");
+    /// cc.set_variable("command", "clang -c $in -o $out");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = clang -c $in -o $out
+    ///   description = CC $out
+    /// "###);
+    /// ```
+    fn set_variable(self, name: impl ToArg, value: impl ToArg) -> Self {
+        self.set_variable_internal(Variable::new(name, value));
+        self
+    }
+
+    /// Remove the variable with the name from the current scope
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// let cc = ninja.rule("cc", "gcc -c $in -o $out");
+    /// cc.build(["foo.o"]).with(["foo.c"])
+    ///     .variable("cflags", "-O2")
+    ///     .remove_variable("cflags");
+    ///
+    /// assert_eq!(ninja.to_string(), r###"
+    /// rule cc
+    ///   command = gcc -c $in -o $out
+    ///
+    /// build foo.o: cc foo.c
+    /// "###);
+    /// ```
+    fn remove_variable(self, name: impl AsRef<str>) -> Self {
+        self.remove_variable_internal(name.as_ref());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::SharedVec;
+    use alloc::vec::Vec;
+
+    /// A type that only implements the required methods
+    struct Scope(SharedVec<Variable>);
+
+    impl Variables for &Scope {
+        fn add_variable_internal(&self, v: Variable) {
+            self.0.add(v);
+        }
+
+        fn remove_variable_internal(&self, name: &str) {
+            self.0.retain(|v| v.name != name);
+        }
+    }
+
+    #[test]
+    fn test_default_set() {
+        let scope = Scope(SharedVec::new());
+        let _ = scope
+            .variable("a", "1")
+            .variable("b", "1")
+            .variable("a", "1")
+            .set_variable("a", "2");
+        let variables: Vec<_> = scope
+            .0
+            .inner()
+            .iter()
+            .map(|v| (v.name.clone(), v.value.escaped().into_owned()))
+            .collect();
+        assert_eq!(
+            variables,
+            [("b".into(), "1".into()), ("a".into(), "2".into())]
+        );
+    }
 }
@@ -0,0 +1,338 @@
+//! Writing the ninja file statement by statement
+
+#[cfg(feature = "std")]
+use alloc::format;
+use core::fmt::{Result, Write};
+
+#[cfg(feature = "std")]
+use crate::bytes::{ByteDecoder, Part};
+use crate::{Ninja, RenderOptions};
+
+impl Ninja {
+    /// Write the ninja file to a [`core::fmt::Write`], statement by statement
+    ///
+    /// The output is the same as [`Display`](core::fmt::Display), without building
+    /// the whole file in one string first.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.rule("cc", "gcc -c $in -o $out").build(["foo.o"]).with(["foo.c"]);
+    ///
+    /// let mut output = String::new();
+    /// ninja.write_to_fmt(&mut output).unwrap();
+    /// assert_eq!(output, ninja.to_string());
+    /// ```
+    pub fn write_to_fmt(&self, writer: &mut impl Write) -> Result {
+        self.render_to(writer, &RenderOptions::default())
+    }
+
+    /// Write the ninja file to a [`std::io::Write`], statement by statement
+    ///
+    /// The output goes through a [`BufWriter`](std::io::BufWriter), and is the same
+    /// as [`Display`](core::fmt::Display), without building the whole file in memory.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.rule("cc", "gcc -c $in -o $out").build(["foo.o"]).with(["foo.c"]);
+    ///
+    /// let mut output = Vec::new();
+    /// ninja.write_to(&mut output).unwrap();
+    /// assert_eq!(output, ninja.to_string().as_bytes());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_to(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        self.write_io(writer, false)
+    }
+
+    /// Write the ninja file through an [`IoWriter`], turning [`Bytes`](crate::Bytes) back
+    /// into the bytes if `bytes` is true, or into `U+FFFD` otherwise
+    #[cfg(feature = "std")]
+    pub(crate) fn write_io(
+        &self,
+        writer: &mut impl std::io::Write,
+        bytes: bool,
+    ) -> std::io::Result<()> {
+        use std::io::Write as _;
+
+        let mut writer = IoWriter {
+            inner: std::io::BufWriter::new(writer),
+            decoder: ByteDecoder::default(),
+            bytes,
+            error: None,
+        };
+        let result = self
+            .render_stored(&mut writer, &RenderOptions::default())
+            .and_then(|_| writer.finish());
+        if result.is_err() {
+            return Err(writer
+                .error
+                .unwrap_or_else(|| std::io::Error::other("formatter error")));
+        }
+        writer.inner.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Ninja {
+    /// Write the ninja file to `path`, only replacing the file if the content changed
+    ///
+    /// The file is rendered (like [`write_bytes`](Self::write_bytes)) to a temporary file
+    /// in the same directory, which is then compared with the existing file. If they
+    /// differ, the temporary file is atomically renamed to `path`, otherwise it is removed
+    /// and the existing file is left untouched, so its modification time doesn't change.
+    /// The permissions of the existing file are kept.
+    /// This is useful for rules marked as [`generator`](crate::RuleVariables::generator)
+    /// that regenerate the ninja file.
+    ///
+    /// Returns if the file was updated.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ninja_writer::*;
+    ///
+    /// let dir = std::env::temp_dir().join("ninja-writer-doc-write-file");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("build.ninja");
+    /// # let _ = std::fs::remove_file(&path);
+    ///
+    /// let ninja = Ninja::new();
+    /// ninja.rule("cc", "gcc -c $in -o $out").build(["foo.o"]).with(["foo.c"]);
+    /// assert!(ninja.write_file(&path).unwrap());
+    /// assert!(!ninja.write_file(&path).unwrap());
+    ///
+    /// ninja.defaults(["foo.o"]);
+    /// assert!(ninja.write_file(&path).unwrap());
+    /// assert_eq!(std::fs::read_to_string(&path).unwrap(), ninja.to_string());
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn write_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<bool> {
+        use std::fs;
+
+        let path = path.as_ref();
+        let (temp_path, mut file) = create_temp_file(path)?;
+        let result = self
+            .write_io(&mut file, true)
+            .and_then(|_| file.sync_all())
+            .and_then(|_| same_content(&temp_path, path))
+            .and_then(|same| {
+                if same {
+                    fs::remove_file(&temp_path)?;
+                } else {
+                    copy_permissions(path, &file)?;
+                    fs::rename(&temp_path, path)?;
+                }
+                Ok(!same)
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+/// Create a new temporary file next to `path`
+#[cfg(feature = "std")]
+fn create_temp_file(
+    path: &std::path::Path,
+) -> std::io::Result<(std::path::PathBuf, std::fs::File)> {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::ffi::OsString;
+    use std::io::ErrorKind;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "path is not a file"))?;
+    loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+        match std::fs::File::create_new(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Copy the permissions of the `existing` file, if it exists, to the new file
+#[cfg(feature = "std")]
+fn copy_permissions(existing: &std::path::Path, new: &std::fs::File) -> std::io::Result<()> {
+    match std::fs::metadata(existing) {
+        Ok(metadata) => new.set_permissions(metadata.permissions()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check if two files have the same content. A missing `existing` file is different.
+#[cfg(feature = "std")]
+fn same_content(new: &std::path::Path, existing: &std::path::Path) -> std::io::Result<bool> {
+    use std::fs::File;
+    use std::io::{BufReader, ErrorKind, Read};
+
+    let existing = match File::open(existing) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let new = File::open(new)?;
+    if new.metadata()?.len() != existing.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut new = BufReader::new(new);
+    let mut existing = BufReader::new(existing);
+    let mut new_buf = [0; 4096];
+    let mut existing_buf = [0; 4096];
+    loop {
+        let n = new.read(&mut new_buf)?;
+        if n == 0 {
+            // the lengths are the same, so the existing file is done too
+            return Ok(true);
+        }
+        existing.read_exact(&mut existing_buf[..n])?;
+        if new_buf[..n] != existing_buf[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Adapter for writing formatted text to a [`std::io::Write`], keeping the I/O error
+#[cfg(feature = "std")]
+struct IoWriter<W: std::io::Write> {
+    inner: std::io::BufWriter<W>,
+    decoder: ByteDecoder,
+    /// If the bytes from [`Bytes`](crate::Bytes) are written as is, instead of `U+FFFD`
+    bytes: bool,
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> IoWriter<W> {
+    /// Write a decoded part of the text
+    fn write_part(&mut self, part: Part) -> std::io::Result<()> {
+        use std::io::Write as _;
+
+        match part {
+            Part::Text(text) => self.inner.write_all(text.as_bytes()),
+            Part::Byte(byte) if self.bytes => self.inner.write_all(&[byte]),
+            Part::Byte(_) => self.inner.write_all("\u{fffd}".as_bytes()),
+        }
+    }
+
+    /// Write the text that is held back by the decoder
+    fn finish(&mut self) -> Result {
+        let mut decoder = core::mem::take(&mut self.decoder);
+        decoder.finish(&mut |part| self.write_part(part).map_err(|e| self.fail(e)))
+    }
+
+    /// Keep the I/O error for returning it later
+    fn fail(&mut self, error: std::io::Error) -> core::fmt::Error {
+        self.error = Some(error);
+        core::fmt::Error
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> Result {
+        let mut decoder = core::mem::take(&mut self.decoder);
+        let result = decoder.decode(s, &mut |part| {
+            self.write_part(part).map_err(|e| self.fail(e))
+        });
+        self.decoder = decoder;
+        result
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    /// A writer that fails after some bytes
+    struct Failing(usize);
+
+    impl std::io::Write for Failing {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(std::io::Error::other("full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_to() {
+        let ninja = Ninja::new();
+        ninja.comment("hello");
+        ninja.variable("x", "y");
+        let rule = ninja.rule("r", "cmd");
+        for i in 0..1000 {
+            rule.build([i.to_string()]);
+        }
+        ninja.defaults(["0"]);
+
+        let mut output = Vec::new();
+        ninja.write_to(&mut output).unwrap();
+        assert_eq!(output, ninja.to_string().as_bytes());
+
+        let error = ninja.write_to(&mut Failing(100)).unwrap_err();
+        assert_eq!(error.to_string(), "full");
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dir = std::env::temp_dir().join(format!("ninja-writer-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        std::fs::write(&path, "old").unwrap();
+
+        let ninja = Ninja::new();
+        ninja.variable("x", "y");
+        assert!(ninja.write_file(&path).unwrap());
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert!(!ninja.write_file(&path).unwrap());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+        // no temporary files are left
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // the permissions are kept
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = |path: &std::path::Path| {
+                std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+            };
+            let permissions = std::fs::Permissions::from_mode(0o640);
+            std::fs::set_permissions(&path, permissions).unwrap();
+            ninja.variable("z", "w");
+            assert!(ninja.write_file(&path).unwrap());
+            assert_eq!(mode(&path), 0o640);
+        }
+        assert!(ninja.write_file(dir.join("missing/build.ninja")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
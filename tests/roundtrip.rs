@@ -0,0 +1,218 @@
+//! Property tests for parsing the output of the builder API
+
+use proptest::prelude::*;
+
+use ninja_writer::*;
+
+/// A statement to generate with the builder API
+#[derive(Debug, Clone)]
+enum GenStmt {
+    Comment(String),
+    Variable(String, String),
+    Rule(String, String, Vec<(String, String)>),
+    /// (rule index, outputs, implicit outputs, inputs, implicit, order-only, validations, variables)
+    Build(
+        usize,
+        Vec<String>,
+        Vec<String>,
+        Vec<String>,
+        Vec<String>,
+        Vec<String>,
+        Vec<String>,
+        Vec<(String, String)>,
+    ),
+    Pool(String, usize, Vec<(String, String)>),
+    Default(Vec<String>),
+    Include(String),
+    Subninja(String),
+}
+
+const KEYWORDS: [&str; 6] = ["build", "rule", "pool", "default", "include", "subninja"];
+
+fn ident() -> impl Strategy<Value = String> {
+    "[a-zA-Z_][a-zA-Z0-9_.-]{0,6}"
+}
+
+fn var_name() -> impl Strategy<Value = String> {
+    ident().prop_filter("keywords are not variable names", |s| {
+        !KEYWORDS.contains(&s.as_str())
+    })
+}
+
+/// Text that needs escaping, but not leading whitespace (which is not preserved by ninja)
+fn value() -> impl Strategy<Value = String> {
+    "([a-z0-9$:|#=]([a-z0-9 $:|#=\n]){0,10})?".prop_map(|s| escape(&s).into_owned())
+}
+
+/// Unescaped path text, which can contain spaces, colons and `$`
+fn raw_path() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9_./ $:-]{1,10}"
+}
+
+fn variables() -> impl Strategy<Value = Vec<(String, String)>> {
+    prop::collection::vec((ident(), value()), 0..4)
+}
+
+/// Options of the ninja file that are not rendered as statements
+#[derive(Debug, Clone)]
+struct Options {
+    /// Pass the paths unescaped with auto-escaping enabled
+    auto_escape: bool,
+    wrap_width: Option<usize>,
+    /// Add build edges and defaults to sections, with keys picked from the rule index
+    sections: bool,
+}
+
+fn options() -> impl Strategy<Value = Options> {
+    (any::<bool>(), prop::option::of(10usize..40), any::<bool>()).prop_map(
+        |(auto_escape, wrap_width, sections)| Options {
+            auto_escape,
+            wrap_width,
+            sections,
+        },
+    )
+}
+
+/// Paths are generated unescaped, see [`paths`]
+fn stmt() -> impl Strategy<Value = GenStmt> {
+    let paths = || prop::collection::vec(raw_path(), 0..4);
+    prop_oneof![
+        "[^\r\n]{0,10}".prop_map(GenStmt::Comment),
+        (var_name(), value()).prop_map(|(n, v)| GenStmt::Variable(n, v)),
+        (ident(), value(), variables()).prop_map(|(n, c, v)| GenStmt::Rule(n, c, v)),
+        (
+            any::<usize>(),
+            // ninja rejects builds without outputs, so they are not rendered but reported
+            // by `validate`, and the output would not parse back to the same statements
+            prop::collection::vec(raw_path(), 1..3),
+            prop::collection::vec(raw_path(), 0..3),
+            paths(),
+            paths(),
+            paths(),
+            paths(),
+            variables(),
+        )
+            .prop_map(|(r, o, io, i, ii, oo, val, v)| GenStmt::Build(r, o, io, i, ii, oo, val, v)),
+        (ident(), any::<usize>(), variables()).prop_map(|(n, d, v)| GenStmt::Pool(n, d, v)),
+        prop::collection::vec(raw_path(), 1..4).prop_map(GenStmt::Default),
+        raw_path().prop_map(GenStmt::Include),
+        raw_path().prop_map(GenStmt::Subninja),
+    ]
+}
+
+/// Escape the paths, unless auto-escaping is enabled
+///
+/// Ninja ends a path at `:` for inputs too, so they need to be escaped the same as outputs.
+fn paths(paths: Vec<String>, options: &Options) -> Vec<String> {
+    if options.auto_escape {
+        return paths;
+    }
+    paths
+        .into_iter()
+        .map(|p| escape_build(&p).into_owned())
+        .collect()
+}
+
+/// Create the ninja file with the builder API
+fn make_ninja(stmts: Vec<GenStmt>, options: &Options) -> Ninja {
+    let mut ninja = Ninja::new();
+    if options.auto_escape {
+        ninja = ninja.auto_escape();
+    }
+    if let Some(width) = options.wrap_width {
+        ninja = ninja.wrap_width(width);
+    }
+    // sections are rendered after the rules they use, which are always added to `ninja`
+    let section = |r: usize| {
+        options
+            .sections
+            .then(|| ninja.section(["b", "a", "c"][r % 3]))
+    };
+    let mut rules = Vec::new();
+    for stmt in stmts {
+        match stmt {
+            GenStmt::Comment(c) => {
+                ninja.comment(c);
+            }
+            GenStmt::Variable(n, v) => {
+                ninja.variable(n, v);
+            }
+            GenStmt::Rule(n, c, vars) => {
+                let mut rule = ninja.rule(n, c);
+                for (n, v) in vars {
+                    rule = rule.variable(n, v);
+                }
+                rules.push(rule);
+            }
+            GenStmt::Build(r, o, io, i, ii, oo, val, vars) => {
+                let o = paths(o, options);
+                let target = section(r);
+                let target = target.as_deref().unwrap_or(&ninja);
+                let mut build = if rules.is_empty() || r % (rules.len() + 1) == 0 {
+                    target.phony(o)
+                } else {
+                    Build::new(&rules[r % (rules.len() + 1) - 1], o).add_to(target)
+                }
+                .output_implicit(paths(io, options))
+                .with(paths(i, options))
+                .with_implicit(paths(ii, options))
+                .with_order_only(paths(oo, options))
+                .validations(paths(val, options));
+                for (n, v) in vars {
+                    build = build.variable(n, v);
+                }
+            }
+            GenStmt::Pool(n, d, vars) => {
+                let mut pool = ninja.pool(n, d);
+                for (n, v) in vars {
+                    pool = pool.variable(n, v);
+                }
+            }
+            GenStmt::Default(p) => {
+                let target = section(p.len());
+                let target = target.as_deref().unwrap_or(&ninja);
+                target.defaults(paths(p, options));
+            }
+            GenStmt::Include(p) => {
+                ninja.include(paths(vec![p], options).remove(0));
+            }
+            GenStmt::Subninja(p) => {
+                ninja.subninja(paths(vec![p], options).remove(0));
+            }
+        }
+    }
+    ninja
+}
+
+proptest! {
+    #[test]
+    fn parse_to_string_is_same(
+        stmts in prop::collection::vec(stmt(), 0..20),
+        options in options(),
+    ) {
+        let ninja = make_ninja(stmts, &options);
+        let output = ninja.to_string();
+        let parsed = Ninja::parse(&output);
+        prop_assert!(parsed.is_ok(), "failed to parse: {:?}\n{}", parsed, output);
+        let parsed = parsed.unwrap();
+        prop_assert_eq!(&parsed, &ninja, "output:\n{}", output);
+        if options.wrap_width.is_none() {
+            // wrapped values are parsed with their line continuations
+            prop_assert_eq!(&parsed.to_string(), &output);
+        }
+        prop_assert_eq!(Cst::parse(&output).unwrap().to_string(), output);
+    }
+}
+
+#[test]
+fn test_escaped_names() {
+    let ninja = Ninja::new();
+    ninja.variable("foo", escape("$bar: baz\n").into_owned());
+    let rule = ninja.rule("cc", "gcc -c $in -o $out");
+    rule.build([escape_build("a b:c$d.o").into_owned()])
+        .with([escape_build("a b:c$d.c").into_owned()]);
+    ninja.defaults([escape_build("a b:c$d.o").into_owned()]);
+
+    let parsed = Ninja::parse(&ninja.to_string()).unwrap();
+    assert_eq!(parsed, ninja);
+}